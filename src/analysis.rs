@@ -1,56 +1,788 @@
-use crate::strategy;
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::{Datelike, NaiveDate, Weekday};
+use itertools::Itertools;
+
+use crate::{
+    loader::MarketData,
+    metrics,
+    model::{DaySeriesData, Price, Stock},
+    strategy,
+    utils::solve_linear,
+};
 
 pub struct AnalysisStrategy {}
 
+/// closes a swing point has to dominate on each side
+const SWING_ORDER: usize = 2;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SwingKind {
+    High,
+    Low,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct SwingPoint {
+    pub date: NaiveDate,
+    pub price: Price,
+    pub kind: SwingKind,
+}
+
+/// two swing points of the same kind with the opposite extreme in between
+#[derive(Debug, Copy, Clone)]
+pub struct DoubleTop {
+    pub first: (NaiveDate, Price),
+    pub second: (NaiveDate, Price),
+    pub neckline: (NaiveDate, Price),
+}
+
+pub type DoubleBottom = DoubleTop;
+
+/// close to close return keyed by the later date
+pub fn daily_returns(stock: &Stock) -> BTreeMap<NaiveDate, f64> {
+    stock
+        .trades
+        .iter()
+        .tuple_windows()
+        .map(|((_, prev), (date, next))| (*date, next.close / prev.close - 1.0))
+        .collect()
+}
+
+/// weekdays between the first and last trade without a bar, holidays included
+pub fn missing_business_days(trades: &BTreeMap<NaiveDate, DaySeriesData>) -> Vec<NaiveDate> {
+    let (Some((first, _)), Some((last, _))) = (trades.first_key_value(), trades.last_key_value())
+    else {
+        return Vec::new();
+    };
+
+    first
+        .iter_days()
+        .take_while(|date| date <= last)
+        .filter(|date| !matches!(date.weekday(), Weekday::Sat | Weekday::Sun))
+        .filter(|date| !trades.contains_key(date))
+        .collect()
+}
+
+/// (date, a return, b return) for dates both stocks have a return on
+pub fn common_returns(a: &Stock, b: &Stock) -> Vec<(NaiveDate, f64, f64)> {
+    let b_returns = daily_returns(b);
+
+    daily_returns(a)
+        .into_iter()
+        .filter_map(|(date, a)| b_returns.get(&date).map(|b| (date, a, *b)))
+        .collect()
+}
+
+/// pearson correlation of the daily returns of every ordered pair of stocks, itself included,
+/// keyed by their codes. pairs with fewer than two common returns are NaN
+pub fn correlation_matrix(stocks: &[Stock]) -> HashMap<(String, String), f64> {
+    stocks
+        .iter()
+        .cartesian_product(stocks)
+        .map(|(a, b)| {
+            let (a_returns, b_returns): (Vec<_>, Vec<_>) = common_returns(a, b)
+                .into_iter()
+                .map(|(_, a, b)| (a, b))
+                .unzip();
+            (
+                (a.code.clone(), b.code.clone()),
+                metrics::correlation(&a_returns, &b_returns),
+            )
+        })
+        .collect()
+}
+
+fn beta_of(returns: &[(NaiveDate, f64, f64)]) -> f64 {
+    let (stock, benchmark): (Vec<_>, Vec<_>) = returns.iter().map(|(_, s, b)| (*s, *b)).unzip();
+    metrics::beta(&stock, &benchmark)
+}
+
+/// (code, dates) of every stock with the dates its close to close return reached `threshold`
+pub fn limit_up_stocks(stocks: &MarketData, threshold: f64) -> Vec<(String, Vec<NaiveDate>)> {
+    stocks
+        .iter()
+        .map(|stock| {
+            let dates = daily_returns(stock)
+                .into_iter()
+                .filter(|(_, ret)| *ret >= threshold)
+                .map(|(date, _)| date)
+                .collect_vec();
+            (stock.code.clone(), dates)
+        })
+        .collect()
+}
+
+/// average close to close return on the trading day after every limit up of `limit_up_stocks`,
+/// limit ups on a stock's last day are skipped. 0.0 without any
+pub fn average_next_day_return_after_limit_up(stocks: &MarketData, threshold: f64) -> f64 {
+    let returns = stocks
+        .iter()
+        .zip(limit_up_stocks(stocks, threshold))
+        .flat_map(|(stock, (_, dates))| event_backtest(stock, &dates, 1).returns)
+        .collect_vec();
+
+    metrics::mean(&returns)
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct EventStats {
+    pub events: usize,
+    pub average_return: f64,
+    pub win_rate: f64,
+    /// forward return of every event in date order
+    pub returns: Vec<f64>,
+}
+
+/// buys the close of every event date and sells the close `hold_days` trading days later,
+/// events without enough following data are skipped
+pub fn event_backtest(stock: &Stock, event_dates: &[NaiveDate], hold_days: usize) -> EventStats {
+    let closes = stock
+        .trades
+        .iter()
+        .map(|(d, t)| (*d, t.close))
+        .collect_vec();
+
+    let returns = event_dates
+        .iter()
+        .sorted()
+        .filter_map(|date| {
+            let ix = closes.binary_search_by_key(date, |(d, _)| *d).ok()?;
+            let (_, exit) = closes.get(ix + hold_days)?;
+            Some(exit / closes[ix].1 - 1.0)
+        })
+        .collect_vec();
+
+    if returns.is_empty() {
+        return EventStats::default();
+    }
+
+    EventStats {
+        events: returns.len(),
+        average_return: metrics::mean(&returns),
+        win_rate: returns.iter().filter(|r| **r > 0.0).count() as f64 / returns.len() as f64,
+        returns,
+    }
+}
+
+#[derive(Debug, Copy, Clone, Default)]
+pub struct StockStats {
+    /// last close over first close, less 1
+    pub total_return: f64,
+    /// population std of the daily returns scaled by sqrt(252)
+    pub annualized_volatility: f64,
+    /// largest daily return, 0.0 without an up day
+    pub max_daily_gain: f64,
+    /// most negative daily return, 0.0 without a down day
+    pub max_daily_loss: f64,
+    pub trading_days: usize,
+}
+
 pub struct StockAnalyzer {}
 
 impl StockAnalyzer {
-    // pub fn evaluate (&self) -> {
+    /// close to close statistics over the whole history of `stock`
+    pub fn evaluate(&self, stock: &Stock) -> StockStats {
+        let (Some((_, first)), Some((_, last))) = (
+            stock.trades.first_key_value(),
+            stock.trades.last_key_value(),
+        ) else {
+            return StockStats::default();
+        };
+        let returns = daily_returns(stock).into_values().collect_vec();
+
+        StockStats {
+            total_return: last.close / first.close - 1.0,
+            annualized_volatility: metrics::std_dev(&returns)
+                * metrics::TRADING_DAYS_PER_YEAR.sqrt(),
+            max_daily_gain: returns.iter().copied().fold(0.0, f64::max),
+            max_daily_loss: returns.iter().copied().fold(0.0, f64::min),
+            trading_days: stock.trades.len(),
+        }
+    }
+
+    /// cov(stock, benchmark) / var(benchmark) of daily returns on common dates
+    pub fn beta(&self, stock: &Stock, benchmark: &Stock) -> f64 {
+        beta_of(&common_returns(stock, benchmark))
+    }
+
+    /// beta over every trailing `window` common returns, keyed by the window's last date, empty
+    /// for a `window` below 2 that has no variance
+    pub fn rolling_beta(
+        &self,
+        stock: &Stock,
+        benchmark: &Stock,
+        window: usize,
+    ) -> BTreeMap<NaiveDate, f64> {
+        if window < 2 {
+            return BTreeMap::new();
+        }
+
+        common_returns(stock, benchmark)
+            .windows(window)
+            .map(|returns| (returns[window - 1].0, beta_of(returns)))
+            .collect()
+    }
+
+    /// `stock.close / index.close` over their common dates, rebased to 100 at the first one,
+    /// rising while the stock outperforms the index
+    pub fn relative_strength_line(&self, stock: &Stock, index: &Stock) -> BTreeMap<NaiveDate, f64> {
+        let ratios = stock
+            .trades
+            .iter()
+            .filter_map(|(date, data)| {
+                let benchmark = index.trades.get(date)?;
+                (benchmark.close != 0.0).then(|| (*date, data.close / benchmark.close))
+            })
+            .collect_vec();
+
+        let Some((_, base)) = ratios.first().copied() else {
+            return BTreeMap::new();
+        };
+
+        ratios
+            .into_iter()
+            .map(|(date, ratio)| (date, ratio / base * 100.0))
+            .collect()
+    }
+
+    /// minimum variance weights (summing to 1, shorts allowed) of a portfolio whose mean daily
+    /// return is `target_return`, from the daily returns on dates every stock has one.
+    /// empty when the covariance matrix is singular or every stock has the same mean
+    pub fn mean_variance_weights(
+        &self,
+        stocks: &[Stock],
+        target_return: f64,
+    ) -> Vec<(String, f64)> {
+        let returns = stocks.iter().map(daily_returns).collect_vec();
+        let Some(first) = returns.first() else {
+            return Vec::new();
+        };
+        let dates = first
+            .keys()
+            .filter(|date| returns.iter().all(|r| r.contains_key(date)))
+            .collect_vec();
+        let series = returns
+            .iter()
+            .map(|r| dates.iter().map(|date| r[*date]).collect_vec())
+            .collect_vec();
+
+        let means = series.iter().map(|s| metrics::mean(s)).collect_vec();
+        let covariance = series
+            .iter()
+            .map(|a| {
+                series
+                    .iter()
+                    .map(|b| metrics::covariance(a, b))
+                    .collect_vec()
+            })
+            .collect_vec();
+
+        // lagrangian of min w'Σw with w'μ = target and w'1 = 1
+        let ones = vec![1.0; stocks.len()];
+        let (Some(inv_ones), Some(inv_means)) = (
+            solve_linear(&covariance, &ones),
+            solve_linear(&covariance, &means),
+        ) else {
+            return Vec::new();
+        };
+
+        let a = inv_ones.iter().sum::<f64>();
+        let b = inv_means.iter().sum::<f64>();
+        let c = means
+            .iter()
+            .zip(&inv_means)
+            .map(|(m, x)| m * x)
+            .sum::<f64>();
+        let d = a * c - b * b;
+        if d == 0.0 {
+            return Vec::new();
+        }
+
+        let (lambda, gamma) = ((c - b * target_return) / d, (a * target_return - b) / d);
+        stocks
+            .iter()
+            .zip(inv_ones.iter().zip(&inv_means))
+            .map(|(stock, (o, m))| (stock.code.clone(), lambda * o + gamma * m))
+            .collect()
+    }
+
+    /// hurst exponent of the daily log returns by rescaled range analysis over chunk sizes
+    /// 8, 16, 32, ..., above 0.5 trending, below mean reverting, 0.5 when there are fewer than
+    /// 32 returns
+    pub fn hurst_exponent(&self, stock: &Stock) -> f64 {
+        let returns = stock
+            .trades
+            .values()
+            .tuple_windows()
+            .filter(|(prev, next)| prev.close > 0.0 && next.close > 0.0)
+            .map(|(prev, next)| (next.close / prev.close).ln())
+            .collect_vec();
+
+        let (log_sizes, log_rs): (Vec<_>, Vec<_>) = std::iter::successors(Some(8), |n| Some(n * 2))
+            .take_while(|n| n * 2 <= returns.len())
+            .filter_map(|n| {
+                let ratios = returns
+                    .chunks_exact(n)
+                    .filter_map(|chunk| {
+                        let mean = metrics::mean(chunk);
+                        let std = metrics::std_dev(chunk);
+                        let deviations = chunk.iter().scan(0.0, |sum, r| {
+                            *sum += r - mean;
+                            Some(*sum)
+                        });
+                        let (min, max) =
+                            deviations.fold((0f64, 0f64), |(min, max), d| (min.min(d), max.max(d)));
+                        (std > 0.0).then(|| (max - min) / std)
+                    })
+                    .collect_vec();
+                (!ratios.is_empty()).then(|| ((n as f64).ln(), metrics::mean(&ratios).ln()))
+            })
+            .unzip();
+
+        if log_sizes.len() < 2 {
+            return 0.5;
+        }
+
+        metrics::covariance(&log_sizes, &log_rs) / metrics::variance(&log_sizes)
+    }
+
+    /// autocorrelation of daily returns at lags `1..=lags`
+    pub fn return_autocorrelation(&self, stock: &Stock, lags: usize) -> Vec<f64> {
+        let returns = daily_returns(stock).into_values().collect_vec();
+        let mean = metrics::mean(&returns);
+        let denominator = returns.iter().map(|r| (r - mean).powi(2)).sum::<f64>();
+
+        (1..=lags)
+            .map(|lag| {
+                if denominator == 0.0 || lag >= returns.len() {
+                    return 0.0;
+                }
+
+                returns
+                    .iter()
+                    .zip(&returns[lag..])
+                    .map(|(prev, next)| (prev - mean) * (next - mean))
+                    .sum::<f64>()
+                    / denominator
+            })
+            .collect()
+    }
+
+    /// swing highs that beat the previous swing high's close while the highest volume within
+    /// `lookback` days around them stays below the previous one's
+    pub fn price_volume_divergence(&self, stock: &Stock, lookback: usize) -> Vec<NaiveDate> {
+        let trades = stock.trades.iter().collect_vec();
+        let volume_peak = |date: NaiveDate| {
+            let ix = trades.partition_point(|(d, _)| **d < date);
+            trades[ix.saturating_sub(lookback)..(ix + lookback + 1).min(trades.len())]
+                .iter()
+                .map(|(_, d)| d.volume)
+                .max()
+                .unwrap_or_default()
+        };
+
+        self.swing_points(stock, SWING_ORDER)
+            .into_iter()
+            .filter(|point| point.kind == SwingKind::High)
+            .map(|point| (point, volume_peak(point.date)))
+            .tuple_windows()
+            .filter(|((prev, prev_volume), (next, next_volume))| {
+                next.price > prev.price && next_volume < prev_volume
+            })
+            .map(|(_, (next, _))| next.date)
+            .collect()
+    }
+
+    /// close strictly above (below) every close `order` days before and after it
+    pub fn swing_points(&self, stock: &Stock, order: usize) -> Vec<SwingPoint> {
+        let trades = stock.trades.iter().collect_vec();
+        let mut result = Vec::new();
+
+        for ix in order..trades.len().saturating_sub(order) {
+            let (date, data) = trades[ix];
+            let around = trades[ix - order..=ix + order]
+                .iter()
+                .enumerate()
+                .filter(|(jx, _)| *jx != order)
+                .map(|(_, (_, d))| d.close)
+                .collect_vec();
+
+            let kind = if around.iter().all(|c| *c < data.close) {
+                SwingKind::High
+            } else if around.iter().all(|c| *c > data.close) {
+                SwingKind::Low
+            } else {
+                continue;
+            };
+
+            result.push(SwingPoint {
+                date: *date,
+                price: data.close,
+                kind,
+            });
+        }
+
+        result
+    }
+
+    /// two swing highs within `tolerance` of each other, neckline is the lowest swing low between
+    pub fn double_tops(&self, stock: &Stock, tolerance: f64) -> Vec<DoubleTop> {
+        self.double_patterns(stock, tolerance, SwingKind::High)
+    }
 
-    // }
+    /// two swing lows within `tolerance` of each other, neckline is the highest swing high between
+    pub fn double_bottoms(&self, stock: &Stock, tolerance: f64) -> Vec<DoubleBottom> {
+        self.double_patterns(stock, tolerance, SwingKind::Low)
+    }
+
+    fn double_patterns(&self, stock: &Stock, tolerance: f64, kind: SwingKind) -> Vec<DoubleTop> {
+        let mut result = Vec::new();
+        let mut last: Option<SwingPoint> = None;
+        let mut neckline: Option<SwingPoint> = None;
+
+        for point in self.swing_points(stock, SWING_ORDER) {
+            if point.kind == kind {
+                if let (Some(first), Some(neck)) = (last, neckline) {
+                    if (point.price - first.price).abs() <= tolerance * first.price.max(point.price)
+                    {
+                        result.push(DoubleTop {
+                            first: (first.date, first.price),
+                            second: (point.date, point.price),
+                            neckline: (neck.date, neck.price),
+                        });
+                    }
+                }
+
+                last = Some(point);
+                neckline = None;
+            } else if last.is_some() {
+                let deeper = match (neckline, kind) {
+                    (None, _) => true,
+                    (Some(neck), SwingKind::High) => point.price < neck.price,
+                    (Some(neck), SwingKind::Low) => point.price > neck.price,
+                };
+
+                if deeper {
+                    neckline = Some(point);
+                }
+            }
+        }
+
+        result
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::collections::BTreeMap;
+
+    use chrono::{Datelike, Weekday};
     use itertools::Itertools;
 
-    use crate::loader::{NasdaqLoader, StockDataLoader};
+    use crate::{
+        fixtures::{date, stock_from_closes, ymd},
+        loader::{MarketData, NasdaqLoader, StockDataLoader},
+        metrics,
+        utils::SplitMix64,
+    };
+
+    use super::{
+        average_next_day_return_after_limit_up, correlation_matrix, daily_returns, event_backtest,
+        limit_up_stocks, missing_business_days, StockAnalyzer,
+    };
+
+    #[test]
+    fn unittest_stock_analyzer_evaluate() {
+        let stats =
+            StockAnalyzer {}.evaluate(&stock_from_closes("A", &[100.0, 110.0, 99.0, 108.9]));
+
+        // returns +10%, -10%, +10% around a mean of 1/30, variance (2 * (2/30)^2 + (4/30)^2) / 3
+        let expected_volatility = (8.0f64).sqrt() / 30.0 * 252f64.sqrt();
+        assert!((stats.total_return - 0.089).abs() < 1e-9);
+        assert!((stats.annualized_volatility - expected_volatility).abs() < 1e-9);
+        assert!((stats.max_daily_gain - 0.1).abs() < 1e-9);
+        assert!((stats.max_daily_loss + 0.1).abs() < 1e-9);
+        assert_eq!(stats.trading_days, 4);
+
+        let empty = StockAnalyzer {}.evaluate(&stock_from_closes("B", &[]));
+        assert_eq!(empty.trading_days, 0);
+        assert_eq!(empty.total_return, 0.0);
+    }
+
+    #[test]
+    fn unittest_correlation_matrix() {
+        let closes = [100.0, 103.0, 101.0, 106.0, 104.0, 108.0];
+        let a = stock_from_closes("A", &closes);
+        // the same returns at twice the price
+        let b = stock_from_closes("B", &closes.map(|close| close * 2.0));
+        let mut c = stock_from_closes("C", &closes.map(|close| 200.0 - close));
+        // only one return in common with the others
+        let d = stock_from_closes("D", &[100.0, 101.0]);
+        c.trades.remove(&date(5));
+
+        let matrix = correlation_matrix(&[a, b, c, d]);
+        let get = |a: &str, b: &str| matrix[&(a.to_owned(), b.to_owned())];
+
+        assert_eq!(matrix.len(), 16);
+        assert!((get("A", "B") - 1.0).abs() < 1e-9);
+        assert!((get("B", "A") - 1.0).abs() < 1e-9);
+        assert!((get("A", "A") - 1.0).abs() < 1e-9);
+        assert!(get("A", "C") < -0.99);
+        assert!(get("A", "D").is_nan());
+    }
+
+    #[test]
+    fn unittest_missing_business_days() {
+        // monday 2024-01-01 to monday 2024-01-15 without weekends and wednesday 2024-01-10
+        let mut trades = stock_from_closes("GAP", &[100.0; 15]).trades;
+        trades.retain(|date, _| {
+            !matches!(date.weekday(), Weekday::Sat | Weekday::Sun) && *date != ymd(2024, 1, 10)
+        });
+
+        assert_eq!(missing_business_days(&trades), vec![ymd(2024, 1, 10)]);
+        assert!(missing_business_days(&BTreeMap::new()).is_empty());
+    }
 
     #[test]
     fn 전날상한가종목_평균상승률() -> eyre::Result<()> {
         let stocks = NasdaqLoader::load()?;
         let 상한가_threashold = 1.0;
 
-        let mut 상한가종목_날짜 = Vec::new();
-
         println!("load complete");
 
-        for stock in stocks {
-            let mut 상한가_dates = Vec::new();
-            for (prev, next) in stock.trades.iter().tuple_windows() {
-                let p = (next.1.close - prev.1.close) / prev.1.close;
+        let mut 상한가종목_날짜 = limit_up_stocks(&stocks, 상한가_threashold);
+        상한가종목_날짜.sort_by_key(|(_, dates)| dates.len());
 
-                if p >= 상한가_threashold {
-                    상한가_dates.push(*next.0);
-                }
-            }
+        for (code, dates) in 상한가종목_날짜.iter().rev().take(30) {
+            println!("{code}, {}, {:?}", dates.len(), dates);
+        }
+
+        println!(
+            "next day: {}",
+            average_next_day_return_after_limit_up(&stocks, 상한가_threashold)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn unittest_limit_up_stocks() {
+        let stocks = MarketData::from(vec![
+            stock_from_closes("A", &[100.0, 200.0, 220.0, 110.0, 220.0, 231.0]),
+            stock_from_closes("B", &[100.0, 150.0]),
+            // no next day to return on
+            stock_from_closes("C", &[50.0, 100.0]),
+        ]);
+
+        assert_eq!(
+            limit_up_stocks(&stocks, 1.0),
+            vec![
+                ("A".to_owned(), vec![date(1), date(4)]),
+                ("B".to_owned(), vec![]),
+                ("C".to_owned(), vec![date(1)]),
+            ]
+        );
+        // +10% after the first and +5% after the second
+        let average = average_next_day_return_after_limit_up(&stocks, 1.0);
+        assert!((average - 0.075).abs() < 1e-9);
+        assert_eq!(average_next_day_return_after_limit_up(&stocks, 2.0), 0.0);
+    }
 
-            // if 상한가_dates.len() > 0 {
-            //     println!("{}, {}, {:?}", stock.name, 상한가_dates.len(), 상한가_dates);
-            // }
+    #[test]
+    fn 전날상한가종목_P이상상승률_종목수() {}
+
+    #[test]
+    fn unittest_double_tops() {
+        let closes = [
+            10.0, 11.0, 12.0, 13.0, 14.0, 15.0, 14.0, 13.0, 12.0, 11.0, 12.0, 13.0, 14.0, 15.1,
+            14.0, 13.0, 12.0, 11.0, 10.0,
+        ];
+        let analyzer = StockAnalyzer {};
+
+        let tops = analyzer.double_tops(&stock_from_closes("M", &closes), 0.02);
+        assert_eq!(tops.len(), 1);
+        assert_eq!(tops[0].first, (date(5), 15.0));
+        assert_eq!(tops[0].second, (date(13), 15.1));
+        assert_eq!(tops[0].neckline, (date(9), 11.0));
+
+        let closes = closes.map(|c| 30.0 - c);
+        let bottoms = analyzer.double_bottoms(&stock_from_closes("W", &closes), 0.02);
+        assert_eq!(bottoms.len(), 1);
+        assert_eq!(bottoms[0].neckline, (date(9), 19.0));
+    }
+
+    #[test]
+    fn unittest_rolling_beta() {
+        let pattern = [0.01, -0.02, 0.015, -0.005, 0.02];
+        let returns = (0..40).map(|ix| pattern[ix % pattern.len()]).collect_vec();
+
+        let closes = |scale: &dyn Fn(usize) -> f64| {
+            returns
+                .iter()
+                .enumerate()
+                .scan(100.0, |close, (ix, r)| {
+                    *close *= 1.0 + r * scale(ix);
+                    Some(*close)
+                })
+                .collect_vec()
+        };
+        let benchmark = stock_from_closes("INDEX", &closes(&|_| 1.0));
+        let stock = stock_from_closes("A", &closes(&|ix| if ix < 20 { 1.0 } else { 2.0 }));
+
+        let analyzer = StockAnalyzer {};
+        let betas = analyzer.rolling_beta(&stock, &benchmark, 10);
+
+        assert!((betas[&date(10)] - 1.0).abs() < 1e-9);
+        assert!((betas[&date(39)] - 2.0).abs() < 1e-9);
+        assert!(analyzer.beta(&stock, &benchmark) > 1.0);
+
+        assert!(analyzer.rolling_beta(&stock, &benchmark, 0).is_empty());
+        assert!(analyzer.rolling_beta(&stock, &benchmark, 1).is_empty());
+    }
+
+    #[test]
+    fn unittest_event_backtest() {
+        let stock = stock_from_closes(
+            "EVT",
+            &[100.0, 101.0, 110.0, 105.0, 100.0, 90.0, 95.0, 81.0],
+        );
+
+        let stats = event_backtest(&stock, &[date(5), date(0), date(7)], 2);
+
+        assert_eq!(stats.events, 2);
+        assert!((stats.returns[0] - 0.1).abs() < 1e-9);
+        assert!((stats.returns[1] + 0.1).abs() < 1e-9);
+        assert!(stats.average_return.abs() < 1e-9);
+        assert_eq!(stats.win_rate, 0.5);
+    }
+
+    #[test]
+    fn unittest_return_autocorrelation() {
+        let closes = (0..50)
+            .map(|ix| if ix % 2 == 0 { 100.0 } else { 102.0 })
+            .collect_vec();
+        let stock = stock_from_closes("MR", &closes);
+
+        let acf = StockAnalyzer {}.return_autocorrelation(&stock, 2);
 
-            상한가종목_날짜.push((stock.name, 상한가_dates));
+        assert_eq!(acf.len(), 2);
+        assert!(acf[0] < -0.9);
+        assert!(acf[1] > 0.9);
+    }
+
+    #[test]
+    fn unittest_price_volume_divergence() {
+        let closes = [
+            10.0, 11.0, 12.0, 15.0, 12.0, 11.0, 10.0, 11.0, 12.0, 16.0, 12.0, 11.0, 10.0,
+        ];
+        let volumes = [
+            1000, 1000, 3000, 5000, 3000, 1000, 1000, 1000, 1500, 2000, 1500, 1000, 1000,
+        ];
+        let mut stock = stock_from_closes("DIV", &closes);
+        for (data, volume) in stock.trades.values_mut().zip(volumes) {
+            data.volume = volume;
         }
 
-        상한가종목_날짜.sort_by_key(|(_, dates)| dates.len());
+        let analyzer = StockAnalyzer {};
+        assert_eq!(analyzer.price_volume_divergence(&stock, 1), vec![date(9)]);
 
-        for (name, dates) in 상한가종목_날짜.iter().rev().take(30) {
-            println!("{name}, {}, {:?}", dates.len(), dates);
+        for data in stock.trades.values_mut() {
+            data.volume = 1000;
         }
+        stock.trades.get_mut(&date(9)).unwrap().volume = 9000;
+        assert!(analyzer.price_volume_divergence(&stock, 1).is_empty());
+    }
 
-        Ok(())
+    #[test]
+    fn unittest_relative_strength_line() {
+        let stock = stock_from_closes(
+            "LEAD",
+            &(0..30).map(|ix| 50.0 * 1.02f64.powi(ix)).collect_vec(),
+        );
+        let mut index = stock_from_closes(
+            "INDEX",
+            &(0..30).map(|ix| 1000.0 * 1.01f64.powi(ix)).collect_vec(),
+        );
+        index.trades.remove(&date(10));
+
+        let line = StockAnalyzer {}.relative_strength_line(&stock, &index);
+
+        assert_eq!(line.len(), 29);
+        assert!(!line.contains_key(&date(10)));
+        assert_eq!(line[&date(0)], 100.0);
+        assert!(line
+            .values()
+            .tuple_windows()
+            .all(|(prev, next)| next > prev));
     }
 
     #[test]
-    fn 전날상한가종목_P이상상승률_종목수() {}
+    fn unittest_mean_variance_weights() {
+        let stock = |code, returns: &[f64]| {
+            let closes = returns
+                .iter()
+                .scan(100.0, |price, r| {
+                    *price *= 1.0 + r;
+                    Some(*price)
+                })
+                .collect_vec();
+            stock_from_closes(code, &[&[100.0], closes.as_slice()].concat())
+        };
+        let a = stock("A", &[0.01, 0.03, -0.01, 0.02, 0.0, 0.01]);
+        let b = stock("B", &[0.0, 0.01, 0.01, -0.005, 0.005, 0.002]);
+        let c = stock("C", &[0.02, -0.01, 0.0, 0.01, 0.015, -0.005]);
+        let analyzer = StockAnalyzer {};
+
+        // with two assets the constraints alone pin the weights
+        let (mean_a, mean_b) = (0.06 / 6.0, 0.022 / 6.0);
+        let weights = analyzer.mean_variance_weights(&[a.clone(), b.clone()], 0.008);
+        let expected_a = (0.008 - mean_b) / (mean_a - mean_b);
+        assert_eq!(weights[0].0, "A");
+        assert!((weights[0].1 - expected_a).abs() < 1e-6);
+        assert!((weights[1].1 - (1.0 - expected_a)).abs() < 1e-6);
+
+        let stocks = [a, b, c];
+        let weights = analyzer.mean_variance_weights(&stocks, 0.006);
+        let means = stocks
+            .iter()
+            .map(|s| metrics::mean(&daily_returns(s).into_values().collect_vec()))
+            .collect_vec();
+        let sum = weights.iter().map(|(_, w)| w).sum::<f64>();
+        let mean = weights
+            .iter()
+            .zip(&means)
+            .map(|((_, w), m)| w * m)
+            .sum::<f64>();
+        assert!((sum - 1.0).abs() < 1e-9);
+        assert!((mean - 0.006).abs() < 1e-9);
+    }
+
+    #[test]
+    fn unittest_hurst_exponent() {
+        let series = |phi: f64| {
+            let mut rng = SplitMix64::new(42);
+            let mut price = 100.0;
+            let mut last = 0.0;
+            let closes = (0..2048)
+                .map(|_| {
+                    last = phi * last + (rng.next_f64() - 0.5) * 0.02;
+                    price *= f64::exp(last);
+                    price
+                })
+                .collect_vec();
+            stock_from_closes("AR", &closes)
+        };
+        let analyzer = StockAnalyzer {};
+
+        let trending = analyzer.hurst_exponent(&series(0.8));
+        let reverting = analyzer.hurst_exponent(&series(-0.8));
+
+        assert!(trending > 0.5, "{trending}");
+        assert!(reverting < trending);
+        assert_eq!(
+            analyzer.hurst_exponent(&stock_from_closes("S", &[1.0; 10])),
+            0.5
+        );
+    }
 }
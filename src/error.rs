@@ -0,0 +1,37 @@
+use std::{io, path::PathBuf};
+
+use thiserror::Error;
+
+/// failures of the loader and the evaluator, converts into `eyre::Report` with `?`
+#[derive(Debug, Error)]
+pub enum StockFilterError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+
+    #[error("{}:{line}: {reason}", .path.display())]
+    Parse {
+        path: PathBuf,
+        line: usize,
+        reason: String,
+    },
+
+    #[error("market data not found at {}", .0.display())]
+    MissingMarketData(PathBuf),
+
+    #[error("trades of {0} have no listing")]
+    MissingTicker(String),
+
+    #[error("no trades to evaluate")]
+    EmptyTrades,
+
+    #[error("no strategy registered as {0}")]
+    UnknownStrategy(String),
+
+    #[error("missing strategy parameter {0}")]
+    MissingParameter(String),
+}
+
+pub type Result<T> = std::result::Result<T, StockFilterError>;
@@ -0,0 +1,75 @@
+use std::collections::BTreeMap;
+
+use chrono::{Days, NaiveDate};
+
+use crate::{
+    model::{DaySeriesData, Price, Stock},
+    strategy::{Action, BuySellStrategy, TargetWeightStrategy},
+};
+
+pub fn date(ix: usize) -> NaiveDate {
+    NaiveDate::from_ymd_opt(2024, 1, 1).unwrap() + Days::new(ix as u64)
+}
+
+pub fn ymd(year: i32, month: u32, day: u32) -> NaiveDate {
+    NaiveDate::from_ymd_opt(year, month, day).unwrap()
+}
+
+pub fn day(close: Price) -> DaySeriesData {
+    DaySeriesData {
+        open: close,
+        high: close,
+        low: close,
+        close,
+        volume: 1000,
+    }
+}
+
+/// one bar per calendar day starting at 2024-01-01, open == high == low == close
+pub fn trades_from_closes(closes: &[Price]) -> BTreeMap<NaiveDate, DaySeriesData> {
+    closes
+        .iter()
+        .enumerate()
+        .map(|(ix, close)| (date(ix), day(*close)))
+        .collect()
+}
+
+pub fn stock_from_closes(code: &str, closes: &[Price]) -> Stock {
+    Stock {
+        code: code.to_owned(),
+        name: code.to_owned(),
+        trades: trades_from_closes(closes),
+        ..Default::default()
+    }
+}
+
+/// replays a fixed action list regardless of the trades it is given, covers are replayed as
+/// buys and shorts as sells
+pub struct FixedStrategy(pub Vec<(NaiveDate, Action)>);
+
+impl BuySellStrategy for FixedStrategy {
+    fn buy(&self, _: &BTreeMap<NaiveDate, DaySeriesData>) -> Vec<(NaiveDate, Action)> {
+        self.0
+            .iter()
+            .filter(|(_, act)| act.is_buy() || act.is_cover())
+            .copied()
+            .collect()
+    }
+
+    fn sell(&self, _: &BTreeMap<NaiveDate, DaySeriesData>) -> Vec<(NaiveDate, Action)> {
+        self.0
+            .iter()
+            .filter(|(_, act)| act.is_sell() || act.is_short())
+            .copied()
+            .collect()
+    }
+}
+
+/// replays a fixed weight schedule regardless of the trades it is given
+pub struct FixedWeights(pub Vec<(NaiveDate, f64)>);
+
+impl TargetWeightStrategy for FixedWeights {
+    fn weights(&self, _: &BTreeMap<NaiveDate, DaySeriesData>) -> Vec<(NaiveDate, f64)> {
+        self.0.clone()
+    }
+}
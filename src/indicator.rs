@@ -0,0 +1,133 @@
+use crate::{
+    model::{DaySeriesData, Price},
+    utils::{efficiency_ratio, kama, rsi, sma},
+};
+
+/// per-bar technical indicator over a chronological bar series
+pub trait Indicator {
+    /// one value per bar, `None` while the indicator is warming up
+    fn values(&self, bars: &[DaySeriesData]) -> Vec<Option<f64>>;
+
+    /// number of trailing bars the last value depends on, `None` if it depends on the whole
+    /// history. `Stock::indicator_as_of` feeds only this many bars, so the last value of
+    /// `values` over them has to equal the last value over the whole series, an indicator with
+    /// recursive smoothing has to keep `None`
+    fn lookback(&self) -> Option<usize> {
+        None
+    }
+}
+
+/// simple moving average of the close
+pub struct Sma {
+    pub period: usize,
+}
+
+impl Indicator for Sma {
+    fn values(&self, bars: &[DaySeriesData]) -> Vec<Option<f64>> {
+        let closes = bars.iter().map(|d| d.close).collect::<Vec<Price>>();
+        sma(&closes, self.period)
+    }
+
+    fn lookback(&self) -> Option<usize> {
+        Some(self.period)
+    }
+}
+
+/// kaufman efficiency ratio of the close
+pub struct EfficiencyRatio {
+    pub period: usize,
+}
+
+impl Indicator for EfficiencyRatio {
+    fn values(&self, bars: &[DaySeriesData]) -> Vec<Option<f64>> {
+        let closes = bars.iter().map(|d| d.close).collect::<Vec<Price>>();
+        efficiency_ratio(&closes, self.period)
+    }
+
+    fn lookback(&self) -> Option<usize> {
+        Some(self.period + 1)
+    }
+}
+
+/// kaufman adaptive moving average of the close
+pub struct Kama {
+    pub period: usize,
+    pub fast: usize,
+    pub slow: usize,
+}
+
+impl Indicator for Kama {
+    fn values(&self, bars: &[DaySeriesData]) -> Vec<Option<f64>> {
+        let closes = bars.iter().map(|d| d.close).collect::<Vec<Price>>();
+        kama(&closes, self.period, self.fast, self.slow)
+    }
+}
+
+/// relative strength index of the close with wilder's smoothing, which carries every earlier
+/// change so the lookback is the whole history
+pub struct Rsi {
+    pub period: usize,
+}
+
+impl Indicator for Rsi {
+    fn values(&self, bars: &[DaySeriesData]) -> Vec<Option<f64>> {
+        let closes = bars.iter().map(|d| d.close).collect::<Vec<Price>>();
+        rsi(&closes, self.period)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fixtures::{date, stock_from_closes};
+
+    use super::{Kama, Rsi};
+
+    #[test]
+    fn unittest_kama() {
+        let kama = Kama {
+            period: 10,
+            fast: 2,
+            slow: 30,
+        };
+
+        let trend = stock_from_closes(
+            "TREND",
+            &(0..60).map(|ix| 100.0 + ix as f64).collect::<Vec<_>>(),
+        );
+        let tracked = trend.indicator(&kama);
+        assert!((trend.trades[&date(59)].close - tracked[&date(59)]).abs() < 2.0);
+
+        let noise = stock_from_closes(
+            "NOISE",
+            &(0..60)
+                .map(|ix| if ix % 2 == 0 { 100.0 } else { 102.0 })
+                .collect::<Vec<_>>(),
+        );
+        let flat = noise.indicator(&kama);
+        let tail = flat.range(date(30)..).map(|(_, v)| *v).collect::<Vec<_>>();
+        let spread = tail.iter().copied().fold(f64::MIN, f64::max)
+            - tail.iter().copied().fold(f64::MAX, f64::min);
+        assert!(spread < 0.2, "{spread}");
+
+        assert_eq!(trend.efficiency_ratio(10)[&date(20)], 1.0);
+        assert!(noise.efficiency_ratio(10)[&date(20)] < 0.1);
+    }
+
+    #[test]
+    fn unittest_rsi_as_of() {
+        let stock = stock_from_closes(
+            "RSI",
+            &(0..40)
+                .map(|ix| 100.0 + (ix % 7) as f64 * if ix % 3 == 0 { -1.5 } else { 1.0 })
+                .collect::<Vec<_>>(),
+        );
+        let rsi = Rsi { period: 14 };
+
+        let full = stock.indicator(&rsi);
+        assert_eq!(full.len(), 40 - 14);
+        assert_eq!(stock.indicator_as_of(&rsi, date(13)), None);
+        for (day, value) in &full {
+            assert_eq!(stock.indicator_as_of(&rsi, *day), Some(*value));
+        }
+    }
+}
@@ -1,16 +1,25 @@
 use std::{
-    collections::{BTreeMap, HashMap},
+    collections::{hash_map::DefaultHasher, BTreeMap, HashMap},
+    fmt::Display,
     fs::{self, File},
+    hash::{Hash, Hasher},
     io::{BufRead, BufReader, BufWriter, Write},
-    path::Path,
+    marker::PhantomData,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::UNIX_EPOCH,
 };
 
 use chrono::NaiveDate;
 use derive_more::{Deref, IntoIterator};
 use itertools::Itertools;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
 use serde::{Deserialize, Serialize};
 
-use crate::model::{DaySeriesData, Stock, StockMarket};
+use crate::{
+    error::{self, StockFilterError},
+    model::{DaySeriesData, Price, Stock, StockMarket},
+};
 
 #[derive(Debug, Deref, Clone, Serialize, Deserialize, IntoIterator)]
 pub struct MarketData(Vec<Stock>);
@@ -22,95 +31,350 @@ impl From<Vec<Stock>> for MarketData {
 }
 
 impl MarketData {
-    pub fn load(path: impl AsRef<Path>) -> eyre::Result<MarketData> {
+    pub fn load(path: impl AsRef<Path>) -> error::Result<MarketData> {
         let file = File::open(path)?;
         let reader = BufReader::new(file);
         Ok(serde_json::from_reader(reader)?)
     }
 
-    pub fn save(&self, path: impl AsRef<Path>) -> eyre::Result<()> {
+    pub fn save(&self, path: impl AsRef<Path>) -> error::Result<()> {
         let file = File::create(path)?;
         let mut writer = BufWriter::new(file);
         serde_json::to_writer(&mut writer, self)?;
         writer.flush()?;
         Ok(())
     }
+
+    /// copy with the trades of every stock cut to the inclusive `[start, end]`, stocks without
+    /// any trades in it are dropped
+    pub fn filter_date_range(&self, start: NaiveDate, end: NaiveDate) -> MarketData {
+        if start > end {
+            return MarketData(Vec::new());
+        }
+
+        self.iter()
+            .filter_map(|stock| {
+                let trades: BTreeMap<_, _> = stock
+                    .trades
+                    .range(start..=end)
+                    .map(|(date, data)| (*date, *data))
+                    .collect();
+
+                (!trades.is_empty()).then(|| Stock {
+                    market: stock.market,
+                    code: stock.code.clone(),
+                    name: stock.name.clone(),
+                    trades,
+                })
+            })
+            .collect_vec()
+            .into()
+    }
+}
+
+/// unit the volume column of the trades files is written in
+#[derive(Default, Debug, Copy, Clone, Hash)]
+pub enum VolumeUnit {
+    #[default]
+    Shares,
+    Thousands,
+    /// traded value (거래대금), converted into shares with the close
+    TradedValue,
+}
+
+impl VolumeUnit {
+    pub fn to_shares(self, volume: f64, close: Price) -> f64 {
+        match self {
+            VolumeUnit::Shares => volume,
+            VolumeUnit::Thousands => volume * 1000.0,
+            VolumeUnit::TradedValue => volume / close,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Hash)]
+pub struct LoaderConfig {
+    pub volume_unit: VolumeUnit,
+    /// directory holding the `{market}.txt` listings and `{market}/` trades directories
+    pub data_dir: PathBuf,
+}
+
+impl Default for LoaderConfig {
+    fn default() -> Self {
+        Self {
+            volume_unit: VolumeUnit::default(),
+            data_dir: PathBuf::from("./data"),
+        }
+    }
 }
 
 pub trait StockDataLoader {
-    fn load() -> eyre::Result<MarketData>;
+    fn load_with(config: &LoaderConfig) -> error::Result<MarketData>;
+
+    fn load() -> error::Result<MarketData> {
+        Self::load_with(&LoaderConfig::default())
+    }
+
+    /// `load` from `dir` instead of `./data`
+    fn load_from(dir: impl AsRef<Path>) -> error::Result<MarketData> {
+        Self::load_with(&LoaderConfig {
+            data_dir: dir.as_ref().to_owned(),
+            ..Default::default()
+        })
+    }
 }
 
+/// every market, nyse is optional and skipped when its data is missing
 pub struct DefaultStockDataLoader {}
 
 impl StockDataLoader for DefaultStockDataLoader {
-    fn load() -> eyre::Result<MarketData> {
-        Ok(vec![
-            load_market(StockMarket::Kospi)?,
-            load_market(StockMarket::Kosdaq)?,
-            load_market(StockMarket::Nasdaq)?,
-        ]
-        .concat()
-        .into())
+    fn load_with(config: &LoaderConfig) -> error::Result<MarketData> {
+        // (market, optional)
+        let markets = [
+            (StockMarket::Kospi, false),
+            (StockMarket::Kosdaq, false),
+            (StockMarket::Nasdaq, false),
+            (StockMarket::Nyse, true),
+        ];
+
+        Ok(markets
+            .into_par_iter()
+            .map(|(market, optional)| match load_market(market, config) {
+                Err(StockFilterError::MissingMarketData(_)) if optional => Ok(Vec::new()),
+                loaded => loaded,
+            })
+            // rayon returns any of the errors, the markets are collected in order to return
+            // the first one
+            .collect::<Vec<_>>()
+            .into_iter()
+            .collect::<error::Result<Vec<_>>>()?
+            .concat()
+            .into())
     }
 }
 
 pub struct KospiLoader {}
 
 impl StockDataLoader for KospiLoader {
-    fn load() -> eyre::Result<MarketData> {
-        Ok(load_market(StockMarket::Kospi)?.into())
+    fn load_with(config: &LoaderConfig) -> error::Result<MarketData> {
+        Ok(load_market(StockMarket::Kospi, config)?.into())
+    }
+}
+
+pub struct KosdaqLoader {}
+
+impl StockDataLoader for KosdaqLoader {
+    fn load_with(config: &LoaderConfig) -> error::Result<MarketData> {
+        Ok(load_market(StockMarket::Kosdaq, config)?.into())
     }
 }
 
 pub struct NasdaqLoader {}
 
 impl StockDataLoader for NasdaqLoader {
-    fn load() -> eyre::Result<MarketData> {
-        Ok(load_market(StockMarket::Nasdaq)?.into())
+    fn load_with(config: &LoaderConfig) -> error::Result<MarketData> {
+        Ok(load_market(StockMarket::Nasdaq, config)?.into())
+    }
+}
+
+pub struct NyseLoader {}
+
+impl StockDataLoader for NyseLoader {
+    fn load_with(config: &LoaderConfig) -> error::Result<MarketData> {
+        Ok(load_market(StockMarket::Nyse, config)?.into())
     }
 }
 
-fn load_market(market: StockMarket) -> eyre::Result<Vec<Stock>> {
+/// `L` behind a json cache of its parsed `MarketData`, reused while neither the files under
+/// the config's `data_dir` nor the config itself changed since it was written
+pub struct CachedLoader<L> {
+    cache_path: PathBuf,
+    loader: PhantomData<L>,
+}
+
+#[derive(Serialize)]
+struct CacheRef<'a> {
+    fingerprint: u64,
+    market_data: &'a MarketData,
+}
+
+#[derive(Deserialize)]
+struct Cache {
+    fingerprint: u64,
+    market_data: MarketData,
+}
+
+impl<L: StockDataLoader> Default for CachedLoader<L> {
+    fn default() -> Self {
+        Self::new("./data.cache.json")
+    }
+}
+
+impl<L: StockDataLoader> CachedLoader<L> {
+    pub fn new(cache_path: impl Into<PathBuf>) -> Self {
+        Self {
+            cache_path: cache_path.into(),
+            loader: PhantomData,
+        }
+    }
+
+    pub fn load(&self) -> error::Result<MarketData> {
+        self.load_with(&LoaderConfig::default())
+    }
+
+    /// the cached data if it is up to date, otherwise parses with `L` and rewrites the cache
+    pub fn load_with(&self, config: &LoaderConfig) -> error::Result<MarketData> {
+        // let the loader report a missing source
+        let Ok(fingerprint) = self.fingerprint(config) else {
+            return L::load_with(config);
+        };
+
+        if let Some(market_data) = self.read_cache(fingerprint) {
+            return Ok(market_data);
+        }
+
+        let market_data = L::load_with(config)?;
+        let file = File::create(&self.cache_path)?;
+        let mut writer = BufWriter::new(file);
+        serde_json::to_writer(
+            &mut writer,
+            &CacheRef {
+                fingerprint,
+                market_data: &market_data,
+            },
+        )?;
+        writer.flush()?;
+
+        Ok(market_data)
+    }
+
+    /// a missing, unreadable or outdated cache is a miss
+    fn read_cache(&self, fingerprint: u64) -> Option<MarketData> {
+        let reader = BufReader::new(File::open(&self.cache_path).ok()?);
+        let cache: Cache = serde_json::from_reader(reader).ok()?;
+        (cache.fingerprint == fingerprint).then_some(cache.market_data)
+    }
+
+    /// hash of the config and the relative path, size and mtime of every file under the source
+    fn fingerprint(&self, config: &LoaderConfig) -> error::Result<u64> {
+        let mut hasher = DefaultHasher::new();
+        config.hash(&mut hasher);
+
+        let mut dirs = vec![config.data_dir.clone()];
+        let mut files = vec![];
+        while let Some(dir) = dirs.pop() {
+            for entry in fs::read_dir(dir)? {
+                let path = entry?.path();
+                if path.is_dir() {
+                    dirs.push(path);
+                } else if path != self.cache_path {
+                    files.push(path);
+                }
+            }
+        }
+        files.sort();
+
+        for path in files {
+            let metadata = fs::metadata(&path)?;
+            let modified = metadata
+                .modified()?
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default();
+            path.strip_prefix(&config.data_dir)
+                .unwrap_or(&path)
+                .hash(&mut hasher);
+            metadata.len().hash(&mut hasher);
+            modified.hash(&mut hasher);
+        }
+
+        Ok(hasher.finish())
+    }
+}
+
+fn load_market(market: StockMarket, config: &LoaderConfig) -> error::Result<Vec<Stock>> {
+    let dir = &config.data_dir;
     let (name, volume_position) = match market {
         StockMarket::Kospi => ("KOSPI", 5),
         StockMarket::Kosdaq => ("KOSDAQ", 5),
         StockMarket::Nasdaq => ("NASDAQ", 6),
-        StockMarket::Nyse => todo!(),
+        StockMarket::Nyse => ("NYSE", 6),
     };
 
-    let mut stocks: HashMap<String, Stock> =
-        load_stocks(format!("./data/{name}.txt"), StockMarket::Kospi)?
-            .into_iter()
-            .map(|s| (s.code.clone(), s))
-            .collect();
-    let trades = fs::read_dir(format!("./data/{name}"))
-        .unwrap()
-        .collect_vec();
+    let listing = dir.join(format!("{name}.txt"));
+    if !listing.is_file() {
+        return Err(StockFilterError::MissingMarketData(listing));
+    }
+
+    let mut stocks: HashMap<String, Stock> = load_stocks(listing, market)?
+        .into_iter()
+        .map(|s| (s.code.clone(), s))
+        .collect();
+    let trades_dir = dir.join(name);
+    let files = fs::read_dir(&trades_dir)
+        .map_err(|_| StockFilterError::MissingMarketData(trades_dir))?
+        .map(|entry| {
+            let entry = entry?;
+            let code = entry.file_name().to_string_lossy().into_owned();
+            if !stocks.contains_key(&code) {
+                return Err(StockFilterError::MissingTicker(code));
+            }
+            Ok((code, entry.path()))
+        })
+        .collect::<error::Result<Vec<_>>>()?;
 
-    for trade in trades {
-        let trade = trade?;
-        let code = trade.file_name().into_string().unwrap();
+    let trades = files
+        .into_par_iter()
+        .map(|(code, path)| {
+            let trades = load_stock_trades(path, volume_position, config.volume_unit)?;
+            Ok((code, trades))
+        })
+        // rayon returns any of the errors, the files are collected in order to return the
+        // first one
+        .collect::<Vec<error::Result<_>>>()
+        .into_iter()
+        .collect::<error::Result<Vec<_>>>()?;
 
-        stocks.get_mut(&code).unwrap().trades = load_stock_trades(trade.path(), volume_position)?;
+    for (code, trades) in trades {
+        stocks.get_mut(&code).unwrap().trades = trades;
     }
 
     Ok(stocks.into_iter().map(|(_, s)| s).collect())
 }
 
-fn load_stocks(path: impl AsRef<Path>, market: StockMarket) -> eyre::Result<Vec<Stock>> {
+/// parses `splits[index]` of the 1-based `line` of `path`, a missing column is a parse error
+/// as well
+fn parse_field<T>(path: &Path, splits: &[&str], index: usize, line: usize) -> error::Result<T>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    let field = splits.get(index).ok_or_else(|| StockFilterError::Parse {
+        path: path.to_owned(),
+        line,
+        reason: format!("missing column {index}"),
+    })?;
+
+    field.parse().map_err(|e| StockFilterError::Parse {
+        path: path.to_owned(),
+        line,
+        reason: format!("column {index} ({field:?}): {e}"),
+    })
+}
+
+fn load_stocks(path: impl AsRef<Path>, market: StockMarket) -> error::Result<Vec<Stock>> {
+    let path = path.as_ref();
     let file = File::open(path)?;
     let reader = BufReader::new(file);
     let mut stocks = vec![];
 
-    for line in reader.lines().into_iter().skip(1) {
+    for (ix, line) in reader.lines().enumerate().skip(1) {
         let line = line?;
         let splits = line.split(',').collect_vec();
 
         stocks.push(Stock {
             market,
-            code: splits[1].to_owned(),
-            name: splits[2].to_owned(),
+            code: parse_field(path, &splits, 1, ix + 1)?,
+            name: parse_field(path, &splits, 2, ix + 1)?,
             ..Default::default()
         })
     }
@@ -121,28 +385,44 @@ fn load_stocks(path: impl AsRef<Path>, market: StockMarket) -> eyre::Result<Vec<
 fn load_stock_trades(
     path: impl AsRef<Path>,
     volume_position: usize,
-) -> eyre::Result<BTreeMap<NaiveDate, DaySeriesData>> {
+    volume_unit: VolumeUnit,
+) -> error::Result<BTreeMap<NaiveDate, DaySeriesData>> {
+    let path = path.as_ref();
     let file = File::open(path)?;
     let reader = BufReader::new(file);
     let mut trades = BTreeMap::new();
 
-    for line in reader.lines().into_iter().skip(1) {
+    for (ix, line) in reader.lines().enumerate().skip(1) {
         let line = line?;
+        let line_no = ix + 1;
         let splits = line.split(',').collect_vec();
 
         // Filter by Nasdaq CRVO (2023-08-18~09-21)
-        if splits[1].len() == 0 {
+        if splits.get(1).is_none_or(|open| open.is_empty()) {
             continue;
         }
 
+        let date = NaiveDate::parse_from_str(splits[0], "%Y-%m-%d").map_err(|e| {
+            StockFilterError::Parse {
+                path: path.to_owned(),
+                line: line_no,
+                reason: format!("date ({:?}): {e}", splits[0]),
+            }
+        })?;
+        let close = parse_field(path, &splits, 4, line_no)?;
+        let volume = volume_unit.to_shares(
+            parse_field::<f64>(path, &splits, volume_position, line_no)?,
+            close,
+        );
+
         trades.insert(
-            NaiveDate::parse_from_str(splits[0], "%Y-%m-%d")?,
+            date,
             DaySeriesData {
-                open: splits[1].parse()?,
-                high: splits[2].parse()?,
-                low: splits[3].parse()?,
-                close: splits[4].parse()?,
-                volume: splits[volume_position].parse::<f64>()? as usize,
+                open: parse_field(path, &splits, 1, line_no)?,
+                high: parse_field(path, &splits, 2, line_no)?,
+                low: parse_field(path, &splits, 3, line_no)?,
+                close,
+                volume: volume.round() as usize,
             },
         );
     }
@@ -157,7 +437,21 @@ fn load_stock_trades(
 
 #[cfg(test)]
 mod tests {
-    use super::{DefaultStockDataLoader, MarketData, StockDataLoader};
+    use std::{
+        fs,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use crate::{
+        error::{self, StockFilterError},
+        fixtures::ymd,
+        model::{DaySeriesData, Stock, StockMarket},
+    };
+
+    use super::{
+        load_market, load_stock_trades, CachedLoader, DefaultStockDataLoader, KospiLoader,
+        LoaderConfig, MarketData, NyseLoader, StockDataLoader, VolumeUnit,
+    };
 
     #[test]
     fn unittest_default_stock_data_loader() -> eyre::Result<()> {
@@ -165,6 +459,28 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn unittest_default_loader_without_nyse() -> eyre::Result<()> {
+        let dir = std::env::temp_dir().join("stock-filter-loader-without-nyse");
+        let _ = fs::remove_dir_all(&dir);
+        for name in ["KOSPI", "KOSDAQ", "NASDAQ"] {
+            fs::create_dir_all(dir.join(name))?;
+            fs::write(dir.join(format!("{name}.txt")), "No,Code,Name\n")?;
+        }
+
+        let loaded = DefaultStockDataLoader::load_from(&dir);
+        fs::remove_file(dir.join("KOSDAQ.txt"))?;
+        let missing = DefaultStockDataLoader::load_from(&dir);
+        fs::remove_dir_all(&dir)?;
+
+        assert!(loaded?.is_empty());
+        assert!(matches!(
+            missing,
+            Err(StockFilterError::MissingMarketData(_))
+        ));
+        Ok(())
+    }
+
     #[test]
     #[ignore = "for ci"]
     fn unittest_default_stock_data_serialize() -> eyre::Result<()> {
@@ -179,4 +495,216 @@ mod tests {
         let _ = MarketData::load("default_stock_data.json")?;
         Ok(())
     }
+
+    #[test]
+    #[ignore = "for ci"]
+    fn unittest_nyse_loader() -> eyre::Result<()> {
+        if !std::path::Path::new("./data/NYSE.txt").is_file() {
+            return Ok(());
+        }
+
+        let market_data = NyseLoader::load()?;
+        assert!(market_data
+            .iter()
+            .all(|stock| stock.market == StockMarket::Nyse));
+        Ok(())
+    }
+
+    #[test]
+    #[ignore = "benchmark"]
+    fn unittest_parallel_load_market() -> eyre::Result<()> {
+        let dir = std::env::temp_dir().join("stock-filter-parallel-load");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("KOSPI"))?;
+
+        let rows = (0..2000)
+            .map(|ix| {
+                let date = ymd(2000, 1, 1) + chrono::Days::new(ix);
+                format!("{date},100,110,90,105,10,0\n")
+            })
+            .collect::<String>();
+        let mut listing = String::from("No,Code,Name\n");
+        for ix in 0..400 {
+            listing.push_str(&format!("{ix},{ix:06},Stock {ix}\n"));
+            fs::write(
+                dir.join("KOSPI").join(format!("{ix:06}")),
+                format!("Date,Open,High,Low,Close,Volume,Change\n{rows}"),
+            )?;
+        }
+        fs::write(dir.join("KOSPI.txt"), listing)?;
+
+        let start = std::time::Instant::now();
+        for entry in fs::read_dir(dir.join("KOSPI"))? {
+            load_stock_trades(entry?.path(), 5, VolumeUnit::Shares)?;
+        }
+        let serial = start.elapsed();
+
+        let start = std::time::Instant::now();
+        let stocks = KospiLoader::load_from(&dir)?;
+        let parallel = start.elapsed();
+        fs::remove_dir_all(&dir)?;
+
+        println!("serial {serial:?}, parallel {parallel:?}");
+        assert_eq!(stocks.len(), 400);
+        assert!(stocks.iter().all(|stock| stock.trades.len() == 2000));
+        Ok(())
+    }
+
+    #[test]
+    fn unittest_filter_date_range() {
+        let stock = |code: &str, days: &[u32]| Stock {
+            code: code.to_owned(),
+            trades: days
+                .iter()
+                .map(|day| (ymd(2024, 1, *day), DaySeriesData::default()))
+                .collect(),
+            ..Default::default()
+        };
+        let market_data =
+            MarketData::from(vec![stock("A", &[1, 2, 3, 4, 5, 6]), stock("B", &[1, 8])]);
+
+        let filtered = market_data.filter_date_range(ymd(2024, 1, 2), ymd(2024, 1, 5));
+
+        assert_eq!(filtered.len(), 1);
+        assert_eq!(filtered[0].code, "A");
+        assert_eq!(
+            filtered[0].trades.keys().copied().collect::<Vec<_>>(),
+            (2..=5).map(|day| ymd(2024, 1, day)).collect::<Vec<_>>()
+        );
+        assert_eq!(market_data[0].trades.len(), 6);
+    }
+
+    #[test]
+    fn unittest_volume_unit() -> eyre::Result<()> {
+        let path = std::env::temp_dir().join("stock-filter-volume-unit.csv");
+        fs::write(
+            &path,
+            "Date,Open,High,Low,Close,Volume,Change\n2024-01-02,100,110,90,105,12.5,0.01\n",
+        )?;
+
+        let thousands = load_stock_trades(&path, 5, VolumeUnit::Thousands)?;
+        fs::remove_file(&path)?;
+
+        assert_eq!(thousands[&ymd(2024, 1, 2)].volume, 12500);
+        assert_eq!(VolumeUnit::TradedValue.to_shares(2100.0, 105.0), 20.0);
+        Ok(())
+    }
+
+    #[test]
+    fn unittest_loader_errors() -> eyre::Result<()> {
+        let dir = std::env::temp_dir().join("stock-filter-loader-errors");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir)?;
+
+        let missing = load_stock_trades(dir.join("nothing.csv"), 5, VolumeUnit::Shares);
+        assert!(matches!(missing, Err(StockFilterError::Io(_))));
+
+        let broken = dir.join("broken.csv");
+        fs::write(
+            &broken,
+            "Date,Open,High,Low,Close,Volume,Change\n2024-01-02,100,110,90,105,10,0\n2024-01-03,100,110,90,abc,10,0\n",
+        )?;
+        let parsed = load_stock_trades(&broken, 5, VolumeUnit::Shares);
+        assert!(matches!(
+            &parsed,
+            Err(StockFilterError::Parse { path, line: 3, .. }) if *path == broken
+        ));
+        let message = eyre::Report::from(parsed.unwrap_err()).to_string();
+        assert!(message.contains(&format!("{}:3", broken.display())));
+        assert!(message.contains("\"abc\""));
+
+        let config = LoaderConfig {
+            data_dir: dir.clone(),
+            ..Default::default()
+        };
+        let market = load_market(StockMarket::Kospi, &config);
+        assert!(matches!(
+            market,
+            Err(StockFilterError::MissingMarketData(_))
+        ));
+
+        fs::write(dir.join("KOSPI.txt"), "No,Code,Name\n1,005930,Samsung\n")?;
+        fs::create_dir_all(dir.join("KOSPI"))?;
+        fs::copy(&broken, dir.join("KOSPI").join("000660"))?;
+        let market = load_market(StockMarket::Kospi, &config);
+
+        // every file is broken, the error of the first one in directory order is returned
+        let codes = (0..16).map(|ix| format!("{ix:06}")).collect::<Vec<_>>();
+        let listing = codes
+            .iter()
+            .enumerate()
+            .map(|(ix, code)| format!("{ix},{code},Stock{ix}\n"))
+            .collect::<String>();
+        fs::write(dir.join("KOSPI.txt"), format!("No,Code,Name\n{listing}"))?;
+        fs::remove_file(dir.join("KOSPI").join("000660"))?;
+        for code in &codes {
+            fs::copy(&broken, dir.join("KOSPI").join(code))?;
+        }
+        let first = fs::read_dir(dir.join("KOSPI"))?.next().unwrap()?.path();
+        let first_errors = (0..10)
+            .map(|_| match load_market(StockMarket::Kospi, &config) {
+                Err(StockFilterError::Parse { path, .. }) => Some(path),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        fs::remove_dir_all(&dir)?;
+
+        assert!(matches!(market, Err(StockFilterError::MissingTicker(code)) if code == "000660"));
+        assert!(first_errors.iter().all(|path| *path == Some(first.clone())));
+        Ok(())
+    }
+
+    static PARSES: AtomicUsize = AtomicUsize::new(0);
+
+    struct CountingLoader {}
+
+    impl StockDataLoader for CountingLoader {
+        fn load_with(config: &LoaderConfig) -> error::Result<MarketData> {
+            PARSES.fetch_add(1, Ordering::SeqCst);
+            KospiLoader::load_with(config)
+        }
+    }
+
+    #[test]
+    fn unittest_cached_loader() -> eyre::Result<()> {
+        let dir = std::env::temp_dir().join("stock-filter-cached-loader");
+        let _ = fs::remove_dir_all(&dir);
+        let data = dir.join("data");
+        fs::create_dir_all(data.join("KOSPI"))?;
+        fs::write(data.join("KOSPI.txt"), "No,Code,Name\n1,005930,Samsung\n")?;
+        let trades = data.join("KOSPI").join("005930");
+        fs::write(
+            &trades,
+            "Date,Open,High,Low,Close,Volume,Change\n2024-01-02,100,110,90,105,10,0\n",
+        )?;
+
+        let loader = CachedLoader::<CountingLoader>::new(dir.join("cache.json"));
+        let config = LoaderConfig {
+            data_dir: data.clone(),
+            ..Default::default()
+        };
+        let first = loader.load_with(&config)?;
+        let second = loader.load_with(&config)?;
+        assert_eq!(PARSES.load(Ordering::SeqCst), 1);
+        assert_eq!(
+            serde_json::to_string(&first)?,
+            serde_json::to_string(&second)?
+        );
+
+        fs::write(
+            &trades,
+            "Date,Open,High,Low,Close,Volume,Change\n2024-01-02,100,110,90,105,10,0\n2024-01-03,105,112,100,110,20,0\n",
+        )?;
+        let changed = loader.load_with(&config)?;
+        let direct = KospiLoader::load_from(&data)?;
+        fs::remove_dir_all(&dir)?;
+
+        assert_eq!(PARSES.load(Ordering::SeqCst), 2);
+        assert_eq!(changed[0].trades.len(), 2);
+        assert_eq!(
+            serde_json::to_string(&changed)?,
+            serde_json::to_string(&direct)?
+        );
+        Ok(())
+    }
 }
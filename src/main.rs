@@ -1,6 +1,13 @@
 mod analysis;
+mod error;
+#[cfg(test)]
+mod fixtures;
+mod indicator;
 mod loader;
+mod metrics;
 mod model;
+mod registry;
+mod resample;
 mod strategy;
 mod utils;
 
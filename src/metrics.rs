@@ -0,0 +1,425 @@
+use std::collections::BTreeMap;
+
+use chrono::{Datelike, NaiveDate};
+use itertools::Itertools;
+
+pub type EquityCurve = BTreeMap<NaiveDate, f64>;
+
+pub const TRADING_DAYS_PER_YEAR: f64 = 252.0;
+
+/// largest peak-to-trough decline as a fraction of the running peak
+pub fn max_drawdown(equity: &EquityCurve) -> f64 {
+    underwater(equity).into_values().fold(0f64, f64::max)
+}
+
+/// day over day returns of the curve, days following a non-positive equity are skipped
+pub fn daily_returns(equity: &EquityCurve) -> Vec<f64> {
+    equity
+        .values()
+        .tuple_windows()
+        .filter(|(prev, _)| **prev > 0.0)
+        .map(|(prev, next)| next / prev - 1.0)
+        .collect()
+}
+
+/// day over day log returns of the curve, pairs with a non-positive equity are skipped
+pub fn log_returns(equity: &EquityCurve) -> Vec<f64> {
+    equity
+        .values()
+        .tuple_windows()
+        .filter(|(prev, next)| **prev > 0.0 && **next > 0.0)
+        .map(|(prev, next)| (next / prev).ln())
+        .collect()
+}
+
+/// largest fall of the log equity from its running peak, `f64::INFINITY` once the equity is
+/// wiped out
+pub fn log_max_drawdown(equity: &EquityCurve) -> f64 {
+    let drawdown = max_drawdown(equity);
+    if drawdown < 1.0 {
+        -(1.0 - drawdown).ln()
+    } else {
+        f64::INFINITY
+    }
+}
+
+/// continuously compounded annual growth rate, the log counterpart of `cagr`
+pub fn log_cagr(equity: &EquityCurve) -> f64 {
+    let (Some((start_date, start)), Some((end_date, end))) =
+        (equity.first_key_value(), equity.last_key_value())
+    else {
+        return 0.0;
+    };
+
+    let years = (*end_date - *start_date).num_days() as f64 / 365.25;
+    if years <= 0.0 || *start <= 0.0 || *end <= 0.0 {
+        return 0.0;
+    }
+
+    (end / start).ln() / years
+}
+
+pub fn mean(values: &[f64]) -> f64 {
+    if values.is_empty() {
+        return 0.0;
+    }
+
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+/// population standard deviation
+pub fn std_dev(values: &[f64]) -> f64 {
+    if values.is_empty() {
+        return 0.0;
+    }
+
+    let mean = mean(values);
+    (values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / values.len() as f64).sqrt()
+}
+
+/// population covariance of two equally long series
+pub fn covariance(a: &[f64], b: &[f64]) -> f64 {
+    if a.is_empty() {
+        return 0.0;
+    }
+
+    let (a_mean, b_mean) = (mean(a), mean(b));
+    a.iter()
+        .zip(b)
+        .map(|(a, b)| (a - a_mean) * (b - b_mean))
+        .sum::<f64>()
+        / a.len() as f64
+}
+
+pub fn variance(values: &[f64]) -> f64 {
+    covariance(values, values)
+}
+
+/// pearson correlation of two equally long series, NaN with fewer than two values or a flat
+/// series
+pub fn correlation(a: &[f64], b: &[f64]) -> f64 {
+    let scale = std_dev(a) * std_dev(b);
+    if a.len() < 2 || scale == 0.0 {
+        return f64::NAN;
+    }
+
+    covariance(a, b) / scale
+}
+
+/// cov(returns, benchmark) / var(benchmark), `0.0` for a flat benchmark
+pub fn beta(returns: &[f64], benchmark: &[f64]) -> f64 {
+    let variance = variance(benchmark);
+    if variance == 0.0 {
+        return 0.0;
+    }
+
+    covariance(returns, benchmark) / variance
+}
+
+/// residual of every strategy return after its `beta * benchmark` share is hedged away
+pub fn hedged_returns(strategy_returns: &[f64], benchmark_returns: &[f64], beta: f64) -> Vec<f64> {
+    strategy_returns
+        .iter()
+        .zip(benchmark_returns)
+        .map(|(ret, benchmark)| ret - beta * benchmark)
+        .collect()
+}
+
+/// annualized `mean / std` of daily returns, `0.0` when the returns never vary
+pub fn sharpe(returns: &[f64]) -> f64 {
+    // the mean of equal values can be off by an ulp, leaving a tiny nonzero std
+    if returns.iter().all_equal() {
+        return 0.0;
+    }
+    let std = std_dev(returns);
+    if std == 0.0 {
+        return 0.0;
+    }
+
+    mean(returns) / std * TRADING_DAYS_PER_YEAR.sqrt()
+}
+
+/// decline from the running peak as a fraction of it on every date
+pub fn underwater(equity: &EquityCurve) -> EquityCurve {
+    let mut peak = f64::MIN;
+
+    equity
+        .iter()
+        .map(|(date, value)| {
+            peak = peak.max(*value);
+            let drawdown = if peak > 0.0 {
+                (peak - value) / peak
+            } else {
+                0.0
+            };
+            (*date, drawdown)
+        })
+        .collect()
+}
+
+/// root mean square of the percentage drawdowns
+pub fn ulcer_index(equity: &EquityCurve) -> f64 {
+    let drawdowns = underwater(equity)
+        .into_values()
+        .map(|d| (d * 100.0).powi(2))
+        .collect_vec();
+
+    mean(&drawdowns).sqrt()
+}
+
+/// (longest run of positive values, longest run of negative values), zero breaks both
+pub fn max_streaks(values: &[f64]) -> (usize, usize) {
+    let (mut wins, mut losses) = (0, 0);
+    let (mut max_wins, mut max_losses) = (0, 0);
+
+    for value in values {
+        if *value > 0.0 {
+            wins += 1;
+            losses = 0;
+        } else if *value < 0.0 {
+            losses += 1;
+            wins = 0;
+        } else {
+            wins = 0;
+            losses = 0;
+        }
+
+        max_wins = max_wins.max(wins);
+        max_losses = max_losses.max(losses);
+    }
+
+    (max_wins, max_losses)
+}
+
+/// fraction of `pnls` that are positive, 0.0 without any
+pub fn win_rate(pnls: &[f64]) -> f64 {
+    if pnls.is_empty() {
+        return 0.0;
+    }
+
+    pnls.iter().filter(|pnl| **pnl > 0.0).count() as f64 / pnls.len() as f64
+}
+
+/// sum of the positive `pnls` over the absolute sum of the negative ones. 0.0 without a
+/// positive one, otherwise infinite without a negative one
+pub fn profit_factor(pnls: &[f64]) -> f64 {
+    let wins = pnls.iter().filter(|pnl| **pnl > 0.0).sum::<f64>();
+    let losses = -pnls.iter().filter(|pnl| **pnl < 0.0).sum::<f64>();
+
+    if wins == 0.0 {
+        0.0
+    } else if losses == 0.0 {
+        f64::INFINITY
+    } else {
+        wins / losses
+    }
+}
+
+/// year -> return of the year, measured from the previous year's last point (or the year's
+/// first point for the first year) to the year's last point
+pub fn yearly_returns(equity: &EquityCurve) -> BTreeMap<i32, f64> {
+    let mut result = BTreeMap::new();
+    let mut start: Option<f64> = None;
+
+    for (year, points) in &equity.iter().group_by(|(date, _)| date.year()) {
+        let points = points.map(|(_, value)| *value).collect_vec();
+        let begin = start.unwrap_or(points[0]);
+        let end = *points.last().unwrap();
+
+        if begin > 0.0 {
+            result.insert(year, end / begin - 1.0);
+        }
+        start = Some(end);
+    }
+
+    result
+}
+
+/// compound annual growth rate between the first and last point of the curve
+pub fn cagr(equity: &EquityCurve) -> f64 {
+    let (Some((start_date, start)), Some((end_date, end))) =
+        (equity.first_key_value(), equity.last_key_value())
+    else {
+        return 0.0;
+    };
+
+    let years = (*end_date - *start_date).num_days() as f64 / 365.25;
+    if years <= 0.0 || *start <= 0.0 || *end <= 0.0 {
+        return 0.0;
+    }
+
+    (end / start).powf(1.0 / years) - 1.0
+}
+
+/// (lower bound, count) of the non-empty `width` wide buckets of `values`, ascending
+pub fn histogram(values: &[f64], width: f64) -> Vec<(f64, usize)> {
+    let mut buckets: BTreeMap<i64, usize> = BTreeMap::new();
+
+    for value in values {
+        *buckets.entry((value / width).floor() as i64).or_default() += 1;
+    }
+
+    buckets
+        .into_iter()
+        .map(|(bucket, count)| (bucket as f64 * width, count))
+        .collect()
+}
+
+/// annualized internal rate of return of dated cashflows (deposits negative, withdrawals and
+/// the final value positive) on an actual/365 basis, `None` when no rate in (-99%, 10000%)
+/// zeroes the net present value
+pub fn irr(cashflows: &[(NaiveDate, f64)]) -> Option<f64> {
+    let start = cashflows.iter().map(|(date, _)| *date).min()?;
+    let npv = |rate: f64| {
+        cashflows
+            .iter()
+            .map(|(date, flow)| {
+                let years = (*date - start).num_days() as f64 / 365.0;
+                flow / (1.0 + rate).powf(years)
+            })
+            .sum::<f64>()
+    };
+
+    let (mut low, mut high) = (-0.99, 100.0);
+    if npv(low).signum() == npv(high).signum() {
+        return None;
+    }
+
+    for _ in 0..200 {
+        let mid = (low + high) / 2.0;
+        if npv(mid).signum() == npv(low).signum() {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+
+    Some((low + high) / 2.0)
+}
+
+/// cagr / max_drawdown, `f64::INFINITY` when there is no drawdown but a positive cagr
+/// and `0.0` when there is neither
+pub fn calmar(cagr: f64, max_drawdown: f64) -> f64 {
+    if max_drawdown == 0.0 {
+        if cagr > 0.0 {
+            f64::INFINITY
+        } else {
+            0.0
+        }
+    } else {
+        cagr / max_drawdown
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fixtures::{date, ymd};
+
+    use super::{
+        beta, cagr, calmar, hedged_returns, irr, max_drawdown, profit_factor, ulcer_index,
+        win_rate, yearly_returns, EquityCurve,
+    };
+
+    #[test]
+    fn unittest_max_drawdown_and_cagr() {
+        let equity: EquityCurve = [
+            (ymd(2024, 1, 1), 100.0),
+            (ymd(2024, 7, 1), 50.0),
+            (ymd(2025, 1, 1), 100.0),
+            (ymd(2026, 1, 1), 121.0),
+        ]
+        .into_iter()
+        .collect();
+
+        assert_eq!(max_drawdown(&equity), 0.5);
+        assert!((cagr(&equity) - 0.1).abs() < 1e-3);
+    }
+
+    #[test]
+    fn unittest_calmar_without_drawdown() {
+        assert_eq!(calmar(0.1, 0.0), f64::INFINITY);
+        assert_eq!(calmar(0.0, 0.0), 0.0);
+        assert_eq!(calmar(0.1, 0.5), 0.2);
+    }
+
+    #[test]
+    fn unittest_profit_factor() {
+        assert_eq!(profit_factor(&[20.0, -5.0, 10.0, 0.0]), 6.0);
+        assert_eq!(profit_factor(&[20.0, 0.0]), f64::INFINITY);
+        assert_eq!(profit_factor(&[-5.0]), 0.0);
+        assert_eq!(profit_factor(&[]), 0.0);
+
+        assert_eq!(win_rate(&[20.0, -5.0, 10.0, 0.0]), 0.5);
+        assert_eq!(win_rate(&[]), 0.0);
+    }
+
+    #[test]
+    fn unittest_ulcer_index() {
+        let curve = |values: &[f64]| -> EquityCurve {
+            values
+                .iter()
+                .enumerate()
+                .map(|(ix, v)| (date(ix), *v))
+                .collect()
+        };
+
+        let smooth = curve(&[100.0, 101.0, 100.5, 102.0, 103.0, 104.0]);
+        let deep = curve(&[100.0, 80.0, 60.0, 50.0, 60.0, 70.0]);
+
+        assert!(ulcer_index(&smooth) < 0.5);
+        assert!(ulcer_index(&deep) > 30.0);
+        assert_eq!(ulcer_index(&curve(&[1.0, 2.0, 3.0])), 0.0);
+    }
+
+    #[test]
+    fn unittest_yearly_returns() {
+        let equity: EquityCurve = [
+            (ymd(2023, 1, 2), 100.0),
+            (ymd(2023, 6, 1), 130.0),
+            (ymd(2023, 12, 29), 110.0),
+            (ymd(2024, 1, 2), 120.0),
+            (ymd(2024, 12, 30), 99.0),
+        ]
+        .into_iter()
+        .collect();
+
+        let returns = yearly_returns(&equity);
+
+        assert_eq!(returns.len(), 2);
+        assert!((returns[&2023] - 0.1).abs() < 1e-9);
+        assert!((returns[&2024] + 0.1).abs() < 1e-9);
+    }
+
+    #[test]
+    fn unittest_irr() {
+        // 1000 compounding two years and 1000 one year at 10%
+        let flows = [
+            (ymd(2021, 1, 1), -1000.0),
+            (ymd(2022, 1, 1), -1000.0),
+            (ymd(2023, 1, 1), 2310.0),
+        ];
+        assert!((irr(&flows).unwrap() - 0.1).abs() < 1e-9);
+
+        let losing = [(ymd(2021, 1, 1), -1000.0), (ymd(2022, 1, 1), 800.0)];
+        assert!((irr(&losing).unwrap() + 0.2).abs() < 1e-9);
+
+        assert_eq!(irr(&[(ymd(2021, 1, 1), -1000.0)]), None);
+    }
+
+    #[test]
+    fn unittest_hedged_returns() {
+        let benchmark = [0.01, -0.02, 0.015, -0.005, 0.02, 0.0, -0.01];
+        let pure_beta = benchmark.map(|r| 1.5 * r);
+
+        let beta = beta(&pure_beta, &benchmark);
+        assert!((beta - 1.5).abs() < 1e-12);
+        assert!(hedged_returns(&pure_beta, &benchmark, beta)
+            .iter()
+            .all(|r| r.abs() < 1e-12));
+
+        let with_alpha = pure_beta.map(|r| r + 0.001);
+        assert!(hedged_returns(&with_alpha, &benchmark, beta)
+            .iter()
+            .all(|r| (r - 0.001).abs() < 1e-12));
+    }
+}
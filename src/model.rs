@@ -1,9 +1,12 @@
 use std::collections::BTreeMap;
 
 use chrono::NaiveDate;
+use itertools::Itertools;
 use serde::{Deserialize, Serialize};
 
-#[derive(Default, Debug, Copy, Clone, Serialize, Deserialize)]
+use crate::indicator::{EfficiencyRatio, Indicator};
+
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum StockMarket {
     #[default]
     Kospi,
@@ -20,8 +23,108 @@ pub struct Stock {
     pub trades: BTreeMap<NaiveDate, DaySeriesData>,
 }
 
+impl Stock {
+    /// (first, last) dates of runs of at least `min_run` identical consecutive closes
+    pub fn stale_runs(&self, min_run: usize) -> Vec<(NaiveDate, NaiveDate)> {
+        let mut result = Vec::new();
+        let mut run: Option<(NaiveDate, NaiveDate, Price, usize)> = None;
+
+        for (date, data) in &self.trades {
+            run = match run {
+                Some((start, _, close, length)) if close == data.close => {
+                    Some((start, *date, close, length + 1))
+                }
+                _ => {
+                    if let Some((start, end, _, length)) = run {
+                        if length >= min_run {
+                            result.push((start, end));
+                        }
+                    }
+                    Some((*date, *date, data.close, 1))
+                }
+            };
+        }
+
+        if let Some((start, end, _, length)) = run {
+            if length >= min_run {
+                result.push((start, end));
+            }
+        }
+
+        result
+    }
+
+    /// parallel date, open, high, low, close and volume columns in date order
+    pub fn ohlc_arrays(&self) -> OhlcArrays {
+        let mut arrays = (
+            Vec::with_capacity(self.trades.len()),
+            Vec::with_capacity(self.trades.len()),
+            Vec::with_capacity(self.trades.len()),
+            Vec::with_capacity(self.trades.len()),
+            Vec::with_capacity(self.trades.len()),
+            Vec::with_capacity(self.trades.len()),
+        );
+
+        for (date, data) in &self.trades {
+            arrays.0.push(*date);
+            arrays.1.push(data.open);
+            arrays.2.push(data.high);
+            arrays.3.push(data.low);
+            arrays.4.push(data.close);
+            arrays.5.push(data.volume);
+        }
+
+        arrays
+    }
+
+    /// full series of `indicator`, warm-up bars are left out
+    pub fn indicator(&self, indicator: &dyn Indicator) -> BTreeMap<NaiveDate, f64> {
+        let bars = self.trades.values().copied().collect::<Vec<_>>();
+
+        self.trades
+            .keys()
+            .zip(indicator.values(&bars))
+            .filter_map(|(date, value)| Some((*date, value?)))
+            .collect()
+    }
+
+    /// kaufman efficiency ratio of the close over `period` days
+    pub fn efficiency_ratio(&self, period: usize) -> BTreeMap<NaiveDate, f64> {
+        self.indicator(&EfficiencyRatio { period })
+    }
+
+    /// value of `indicator` at the last bar on or before `date`, only the bars up to `date`
+    /// (or its lookback window of them) are fed to the indicator
+    pub fn indicator_as_of(&self, indicator: &dyn Indicator, date: NaiveDate) -> Option<f64> {
+        let lookback = indicator.lookback().unwrap_or(usize::MAX);
+        let mut bars = self
+            .trades
+            .range(..=date)
+            .rev()
+            .take(lookback)
+            .map(|(_, d)| *d)
+            .collect_vec();
+        bars.reverse();
+
+        *indicator.values(&bars).last()?
+    }
+}
+
 pub type Price = f64;
 
+/// share counts, fractional when buys are sized by cash
+pub type Shares = f64;
+
+/// (dates, opens, highs, lows, closes, volumes)
+pub type OhlcArrays = (
+    Vec<NaiveDate>,
+    Vec<Price>,
+    Vec<Price>,
+    Vec<Price>,
+    Vec<Price>,
+    Vec<usize>,
+);
+
 #[derive(Default, Debug, Copy, Clone, Serialize, Deserialize)]
 pub struct DaySeriesData {
     pub open: Price,
@@ -30,3 +133,73 @@ pub struct DaySeriesData {
     pub close: Price,
     pub volume: usize,
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        fixtures::{date, stock_from_closes},
+        indicator::Sma,
+    };
+
+    #[test]
+    fn unittest_stale_runs() {
+        let closes = (0..30)
+            .map(|ix| {
+                if (10..20).contains(&ix) {
+                    50.0
+                } else {
+                    ix as f64
+                }
+            })
+            .collect::<Vec<_>>();
+        let stock = stock_from_closes("HALT", &closes);
+
+        assert_eq!(stock.stale_runs(5), vec![(date(10), date(19))]);
+        assert!(stock.stale_runs(11).is_empty());
+    }
+
+    #[test]
+    fn unittest_indicator_as_of() {
+        let closes = (0..40)
+            .map(|ix| 100.0 + (ix % 7) as f64)
+            .collect::<Vec<_>>();
+        let stock = stock_from_closes("SMA", &closes);
+        let sma = Sma { period: 5 };
+        let series = stock.indicator(&sma);
+
+        assert_eq!(
+            stock.indicator_as_of(&sma, date(25)),
+            Some(series[&date(25)])
+        );
+        assert_eq!(
+            stock.indicator_as_of(&sma, date(39)),
+            Some(series[&date(39)])
+        );
+        assert_eq!(
+            stock.indicator_as_of(&sma, date(100)),
+            Some(series[&date(39)])
+        );
+        assert_eq!(stock.indicator_as_of(&sma, date(2)), None);
+    }
+
+    #[test]
+    fn unittest_ohlc_arrays() {
+        let mut stock = stock_from_closes("OHLC", &[3.0, 1.0, 2.0]);
+        for (ix, data) in stock.trades.values_mut().enumerate() {
+            data.open = data.close - 0.5;
+            data.high = data.close + 1.0;
+            data.low = data.close - 1.0;
+            data.volume = ix * 10;
+        }
+
+        let (dates, opens, highs, lows, closes, volumes) = stock.ohlc_arrays();
+
+        assert_eq!(dates, vec![date(0), date(1), date(2)]);
+        assert!(dates.windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(closes, vec![3.0, 1.0, 2.0]);
+        assert_eq!(opens, vec![2.5, 0.5, 1.5]);
+        assert_eq!(highs, vec![4.0, 2.0, 3.0]);
+        assert_eq!(lows, vec![2.0, 0.0, 1.0]);
+        assert_eq!(volumes, vec![0, 10, 20]);
+    }
+}
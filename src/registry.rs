@@ -0,0 +1,239 @@
+use std::collections::HashMap;
+
+use crate::{
+    error::{self, StockFilterError},
+    strategy::{
+        AtrStopStrategy, BeginningSurpriseStrategy, BollingerStrategy, BuySellStrategy,
+        KaufmanAmaStrategy, MacdStrategy, MovingAverageCrossStrategy, NaiveStrategy,
+        OpeningRangeBreakoutStrategy, RegressionChannelStrategy, RsiStrategy,
+        TrendPullbackStrategy, VolumeSpikeStrategy, ZScoreStrategy,
+    },
+};
+
+/// parameter name -> value given to a strategy factory
+pub type StrategyParams = HashMap<String, f64>;
+
+type StrategyFactory = Box<dyn Fn(&StrategyParams) -> error::Result<Box<dyn BuySellStrategy>>>;
+
+/// strategies by name string, for callers that only know the name, e.g. a cli
+#[derive(Default)]
+pub struct StrategyRegistry {
+    factories: HashMap<String, StrategyFactory>,
+}
+
+impl StrategyRegistry {
+    /// registry with the strategies of this crate that are fully described by numbers
+    pub fn builtin() -> Self {
+        let mut registry = Self::default();
+
+        registry.register("naive", |params| {
+            Ok(Box::new(NaiveStrategy {
+                buy_move: param(params, "buy_move")? as usize,
+                sell_move: param(params, "sell_move")? as usize,
+                breakout_margin: params.get("breakout_margin").copied().unwrap_or(0.0),
+            }))
+        });
+        registry.register("regression_channel", |params| {
+            Ok(Box::new(RegressionChannelStrategy {
+                period: param(params, "period")? as usize,
+                num_std: param(params, "num_std")?,
+            }))
+        });
+        registry.register("trend_pullback", |params| {
+            Ok(Box::new(TrendPullbackStrategy {
+                trend_period: param(params, "trend_period")? as usize,
+                pullback_period: param(params, "pullback_period")? as usize,
+            }))
+        });
+        registry.register("opening_range_breakout", |params| {
+            Ok(Box::new(OpeningRangeBreakoutStrategy {
+                range_pct: param(params, "range_pct")?,
+            }))
+        });
+        registry.register("beginning_surprise", |params| {
+            Ok(Box::new(BeginningSurpriseStrategy {
+                rise: param(params, "rise")?,
+            }))
+        });
+        registry.register("moving_average_cross", |params| {
+            Ok(Box::new(MovingAverageCrossStrategy {
+                short: param(params, "short")? as usize,
+                long: param(params, "long")? as usize,
+                use_ema: params.get("use_ema").is_some_and(|value| *value != 0.0),
+            }))
+        });
+        registry.register("kaufman_ama", |params| {
+            Ok(Box::new(KaufmanAmaStrategy {
+                period: param(params, "period")? as usize,
+                fast: param(params, "fast")? as usize,
+                slow: param(params, "slow")? as usize,
+            }))
+        });
+        registry.register("rsi", |params| {
+            Ok(Box::new(RsiStrategy {
+                period: param(params, "period")? as usize,
+                oversold: param(params, "oversold")?,
+                overbought: param(params, "overbought")?,
+            }))
+        });
+        registry.register("macd", |params| {
+            Ok(Box::new(MacdStrategy {
+                fast: param(params, "fast")? as usize,
+                slow: param(params, "slow")? as usize,
+                signal: param(params, "signal")? as usize,
+            }))
+        });
+        registry.register("bollinger", |params| {
+            Ok(Box::new(BollingerStrategy {
+                period: param(params, "period")? as usize,
+                num_std: param(params, "num_std")?,
+            }))
+        });
+        registry.register("atr_stop", |params| {
+            Ok(Box::new(AtrStopStrategy {
+                atr_period: param(params, "atr_period")? as usize,
+                multiple: param(params, "multiple")?,
+            }))
+        });
+        registry.register("volume_spike", |params| {
+            Ok(Box::new(VolumeSpikeStrategy {
+                lookback: param(params, "lookback")? as usize,
+                multiple: param(params, "multiple")?,
+            }))
+        });
+        registry.register("zscore", |params| {
+            Ok(Box::new(ZScoreStrategy {
+                window: param(params, "window")? as usize,
+                entry: param(params, "entry")?,
+                exit: param(params, "exit")?,
+            }))
+        });
+
+        registry
+    }
+
+    /// registers `factory` as `name`, replacing any factory registered before
+    pub fn register<F>(&mut self, name: &str, factory: F)
+    where
+        F: Fn(&StrategyParams) -> error::Result<Box<dyn BuySellStrategy>> + 'static,
+    {
+        self.factories.insert(name.to_owned(), Box::new(factory));
+    }
+
+    pub fn build_by_name(
+        &self,
+        name: &str,
+        params: &StrategyParams,
+    ) -> error::Result<Box<dyn BuySellStrategy>> {
+        let factory = self
+            .factories
+            .get(name)
+            .ok_or_else(|| StockFilterError::UnknownStrategy(name.to_owned()))?;
+        factory(params)
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        let mut names = self
+            .factories
+            .keys()
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+}
+
+/// `params[name]`, missing parameters are an error
+pub fn param(params: &StrategyParams, name: &str) -> error::Result<f64> {
+    params
+        .get(name)
+        .copied()
+        .ok_or_else(|| StockFilterError::MissingParameter(name.to_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        error::StockFilterError,
+        fixtures::trades_from_closes,
+        strategy::{BuySellStrategy, NaiveStrategy, RsiStrategy},
+    };
+
+    use super::{param, StrategyParams, StrategyRegistry};
+
+    #[test]
+    fn unittest_build_by_name() {
+        let mut registry = StrategyRegistry::default();
+        registry.register("naive", |params| {
+            Ok(Box::new(NaiveStrategy {
+                buy_move: param(params, "buy_move")? as usize,
+                sell_move: param(params, "sell_move")? as usize,
+                breakout_margin: params.get("breakout_margin").copied().unwrap_or(0.0),
+            }))
+        });
+
+        let params: StrategyParams = [("buy_move".to_owned(), 3.0), ("sell_move".to_owned(), 4.0)]
+            .into_iter()
+            .collect();
+        let strategy = registry.build_by_name("naive", &params).unwrap();
+
+        let closes = (0..40)
+            .map(|ix| 100.0 + ((ix * 7) % 11) as f64)
+            .collect::<Vec<_>>();
+        let trades = trades_from_closes(&closes);
+        let direct = NaiveStrategy {
+            buy_move: 3,
+            sell_move: 4,
+            breakout_margin: 0.0,
+        };
+        assert_eq!(strategy.buy_sell(&trades), direct.buy_sell(&trades));
+
+        assert!(matches!(
+            registry.build_by_name("unknown", &params),
+            Err(StockFilterError::UnknownStrategy(_))
+        ));
+        assert!(matches!(
+            registry.build_by_name("naive", &StrategyParams::new()),
+            Err(StockFilterError::MissingParameter(name)) if name == "buy_move"
+        ));
+
+        let builtin = StrategyRegistry::builtin();
+        assert_eq!(
+            builtin.names(),
+            vec![
+                "atr_stop",
+                "beginning_surprise",
+                "bollinger",
+                "kaufman_ama",
+                "macd",
+                "moving_average_cross",
+                "naive",
+                "opening_range_breakout",
+                "regression_channel",
+                "rsi",
+                "trend_pullback",
+                "volume_spike",
+                "zscore",
+            ]
+        );
+        let params: StrategyParams = [
+            ("period".to_owned(), 14.0),
+            ("oversold".to_owned(), 30.0),
+            ("overbought".to_owned(), 70.0),
+        ]
+        .into_iter()
+        .collect();
+        let direct = RsiStrategy {
+            period: 14,
+            oversold: 30.0,
+            overbought: 70.0,
+        };
+        assert_eq!(
+            builtin
+                .build_by_name("rsi", &params)
+                .unwrap()
+                .buy_sell(&trades),
+            direct.buy_sell(&trades)
+        );
+    }
+}
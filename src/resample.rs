@@ -0,0 +1,92 @@
+use std::collections::BTreeMap;
+
+use chrono::{Datelike, Days, NaiveDate};
+use serde::{Deserialize, Serialize};
+
+use crate::model::DaySeriesData;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Resample {
+    /// weeks starting on monday
+    Weekly,
+    Monthly,
+}
+
+impl Resample {
+    /// first calendar day of the period `date` is in
+    pub fn period_start(&self, date: NaiveDate) -> NaiveDate {
+        match self {
+            Resample::Weekly => date - Days::new(date.weekday().num_days_from_monday() as u64),
+            Resample::Monthly => date.with_day(1).unwrap(),
+        }
+    }
+}
+
+/// one bar per period keyed by its first calendar day, with the first open, highest high,
+/// lowest low, last close and summed volume of the daily bars in it
+pub fn resample(
+    trades: &BTreeMap<NaiveDate, DaySeriesData>,
+    period: Resample,
+) -> BTreeMap<NaiveDate, DaySeriesData> {
+    let mut result: BTreeMap<NaiveDate, DaySeriesData> = BTreeMap::new();
+
+    for (date, data) in trades {
+        result
+            .entry(period.period_start(*date))
+            .and_modify(|bar| {
+                bar.high = bar.high.max(data.high);
+                bar.low = bar.low.min(data.low);
+                bar.close = data.close;
+                bar.volume += data.volume;
+            })
+            .or_insert(*data);
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Datelike, Weekday};
+
+    use crate::fixtures::{date, trades_from_closes, ymd};
+
+    use super::{resample, Resample};
+
+    #[test]
+    fn unittest_resample() {
+        // monday 2024-01-01 to sunday 2024-02-04
+        let mut trades =
+            trades_from_closes(&(0..35).map(|ix| 100.0 + ix as f64).collect::<Vec<_>>());
+        for (ix, bar) in trades.values_mut().enumerate() {
+            bar.open = bar.close - 1.0;
+            bar.high = bar.close + [3.0, 1.0, 5.0, 1.0, 2.0, 1.0, 1.0][ix % 7];
+            bar.low = bar.close - [2.0, 4.0, 1.0, 1.0, 3.0, 1.0, 1.0][ix % 7];
+            bar.volume = 100 * (ix + 1);
+        }
+        let week = trades
+            .range(date(0)..date(5))
+            .map(|(d, b)| (*d, *b))
+            .collect();
+
+        let weekly = resample(&week, Resample::Weekly);
+        assert_eq!(weekly.len(), 1);
+        let bar = weekly[&date(0)];
+        assert_eq!(
+            (bar.open, bar.high, bar.low, bar.close, bar.volume),
+            (99.0, 107.0, 97.0, 104.0, 1500)
+        );
+
+        let weekly = resample(&trades, Resample::Weekly);
+        assert_eq!(weekly.len(), 5);
+        assert!(weekly.keys().all(|day| day.weekday() == Weekday::Mon));
+
+        let monthly = resample(&trades, Resample::Monthly);
+        assert_eq!(
+            monthly.keys().copied().collect::<Vec<_>>(),
+            vec![ymd(2024, 1, 1), ymd(2024, 2, 1)]
+        );
+        assert_eq!(monthly[&ymd(2024, 2, 1)].open, 130.0);
+        assert_eq!(monthly[&ymd(2024, 1, 1)].close, 130.0);
+    }
+}
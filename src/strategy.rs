@@ -1,23 +1,38 @@
 use std::{
     borrow::BorrowMut,
     cmp::max,
-    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque},
+    io::Write,
+    mem,
+    sync::Arc,
 };
 
-use chrono::NaiveDate;
+use chrono::{Datelike, NaiveDate};
 use itertools::Itertools;
 use moving_min_max::{MovingMax, MovingMin};
-use std::ops::Bound::{Included, Unbounded};
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    model::{DaySeriesData, Price, Stock},
-    utils::MovingAverage,
+    analysis,
+    error::{self, StockFilterError},
+    metrics::{self, EquityCurve},
+    model::{DaySeriesData, Price, Shares, Stock, StockMarket},
+    utils::{
+        atr, ema, kama, lossless_float, rsi, sma, MovingAverage, MovingRegression, MovingStdDev,
+        SplitMix64,
+    },
 };
 
-#[derive(Debug, Copy, Clone)]
+/// serialized as `{"type":"Buy","price":123.0}`
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "price")]
 pub enum Action {
     Buy(Price),
     Sell(Price),
+    /// sells borrowed shares, profits when the price falls
+    Short(Price),
+    /// buys the shorted shares back
+    Cover(Price),
 }
 
 impl Action {
@@ -34,6 +49,23 @@ impl Action {
             _ => false,
         }
     }
+
+    pub fn is_short(&self) -> bool {
+        matches!(self, Action::Short(_))
+    }
+
+    pub fn is_cover(&self) -> bool {
+        matches!(self, Action::Cover(_))
+    }
+
+    pub fn price(&self) -> Price {
+        match self {
+            Action::Buy(price)
+            | Action::Sell(price)
+            | Action::Short(price)
+            | Action::Cover(price) => *price,
+        }
+    }
 }
 
 pub trait BuySellStrategy {
@@ -55,11 +87,27 @@ pub trait BuySellStrategy {
     }
 }
 
-/// buy: 현재 주가가 buy_move 일 최저가보다 작다
-/// sell: 현재 주가가 sell_move 일 최고가보다 크다
+impl<S: BuySellStrategy + ?Sized> BuySellStrategy for Box<S> {
+    fn buy(&self, trades: &BTreeMap<NaiveDate, DaySeriesData>) -> Vec<(NaiveDate, Action)> {
+        (**self).buy(trades)
+    }
+
+    fn sell(&self, trades: &BTreeMap<NaiveDate, DaySeriesData>) -> Vec<(NaiveDate, Action)> {
+        (**self).sell(trades)
+    }
+
+    fn buy_sell(&self, trades: &BTreeMap<NaiveDate, DaySeriesData>) -> Vec<(NaiveDate, Action)> {
+        (**self).buy_sell(trades)
+    }
+}
+
+/// buy: 현재 주가가 buy_move 일 최저가보다 breakout_margin 이상 작다
+/// sell: 현재 주가가 sell_move 일 최고가보다 breakout_margin 이상 크다
 pub struct NaiveStrategy {
     pub buy_move: usize,
     pub sell_move: usize,
+    /// fraction of the window min/max the open has to break it by, 0.0 fires on any breakout
+    pub breakout_margin: f64,
 }
 
 impl BuySellStrategy for NaiveStrategy {
@@ -73,7 +121,7 @@ impl BuySellStrategy for NaiveStrategy {
                 continue;
             }
 
-            if data.open < *slide.min().unwrap() {
+            if data.open < *slide.min().unwrap() * (1.0 - self.breakout_margin) {
                 result.push((*date, Action::Buy(data.open)));
             }
 
@@ -89,12 +137,12 @@ impl BuySellStrategy for NaiveStrategy {
         let mut result = Vec::new();
 
         for (ix, (date, data)) in trades.iter().enumerate() {
-            if ix < self.buy_move {
+            if ix < self.sell_move {
                 slide.push(data.close);
                 continue;
             }
 
-            if *slide.max().unwrap() < data.open {
+            if *slide.max().unwrap() * (1.0 + self.breakout_margin) < data.open {
                 result.push((*date, Action::Sell(data.open)));
             }
 
@@ -106,275 +154,5041 @@ impl BuySellStrategy for NaiveStrategy {
     }
 }
 
-/// buy:
-pub struct NaiveMovingAverageStrategy {}
-
-/// buy: 전날 rise % 만큼 을랐다
-/// sell: 없음
-pub struct BeginningSurpriseStrategy {
-    rise: f64,
+/// buy: 단기 이동평균이 장기 이동평균 위로 올라섰다 (골든크로스)
+/// sell: 단기 이동평균이 장기 이동평균 아래로 내려갔다 (데드크로스)
+pub struct MovingAverageCrossStrategy {
+    pub short: usize,
+    pub long: usize,
+    /// exponential instead of simple averages
+    pub use_ema: bool,
 }
 
-pub trait FoldStrategy
-where
-    Self: 'static,
-{
-    fn fold(
-        &self,
-        actions: Vec<(NaiveDate, Action)>,
-        trades: &BTreeMap<NaiveDate, DaySeriesData>,
-    ) -> Vec<(NaiveDate, Action)>;
+/// (date, close, short average - long average)
+type CrossBar = (NaiveDate, Price, f64);
 
-    fn boxed(self) -> Box<dyn FoldStrategy>
-    where
-        Self: Sized,
-    {
-        Box::new(self)
+impl MovingAverageCrossStrategy {
+    /// pairs of consecutive bars with both averages
+    fn crossings(&self, trades: &BTreeMap<NaiveDate, DaySeriesData>) -> Vec<(CrossBar, CrossBar)> {
+        let closes = trades.values().map(|d| d.close).collect_vec();
+        let average = if self.use_ema { ema } else { sma };
+
+        trades
+            .iter()
+            .zip(
+                average(&closes, self.short)
+                    .into_iter()
+                    .zip(average(&closes, self.long)),
+            )
+            .filter_map(|((date, data), (short, long))| Some((*date, data.close, short? - long?)))
+            .tuple_windows()
+            .collect()
     }
 }
 
-pub struct NeverSellStrategy {}
-
-impl FoldStrategy for NeverSellStrategy {
-    fn fold(
-        &self,
-        actions: Vec<(NaiveDate, Action)>,
-        _: &BTreeMap<NaiveDate, DaySeriesData>,
-    ) -> Vec<(NaiveDate, Action)> {
-        actions
+impl BuySellStrategy for MovingAverageCrossStrategy {
+    fn buy(&self, trades: &BTreeMap<NaiveDate, DaySeriesData>) -> Vec<(NaiveDate, Action)> {
+        self.crossings(trades)
             .into_iter()
-            .filter(|(_, act)| !act.is_sell())
+            .filter(|((_, _, prev), (_, _, spread))| *prev <= 0.0 && *spread > 0.0)
+            .map(|(_, (date, close, _))| (date, Action::Buy(close)))
             .collect()
     }
-}
 
-pub struct ConsecutiveBuyRemover {}
-
-impl FoldStrategy for ConsecutiveBuyRemover {
-    fn fold(
-        &self,
-        actions: Vec<(NaiveDate, Action)>,
-        _: &BTreeMap<NaiveDate, DaySeriesData>,
-    ) -> Vec<(NaiveDate, Action)> {
-        actions
+    fn sell(&self, trades: &BTreeMap<NaiveDate, DaySeriesData>) -> Vec<(NaiveDate, Action)> {
+        self.crossings(trades)
             .into_iter()
-            .dedup_by(|(_, lact), (_, ract)| {
-                (lact.is_buy() && ract.is_buy()) || (lact.is_sell() && ract.is_sell())
-            })
+            .filter(|((_, _, prev), (_, _, spread))| *prev >= 0.0 && *spread < 0.0)
+            .map(|(_, (date, close, _))| (date, Action::Sell(close)))
             .collect()
     }
 }
 
-pub struct LossSellRemover {}
+/// buy: 현재 주가가 지난 period 일 회귀직선보다 num_std 표준편차 이상 낮다
+/// sell: 현재 주가가 지난 period 일 회귀직선보다 num_std 표준편차 이상 높다
+pub struct RegressionChannelStrategy {
+    pub period: usize,
+    pub num_std: f64,
+}
 
-impl FoldStrategy for LossSellRemover {
-    fn fold(
+impl RegressionChannelStrategy {
+    /// (date, close, line, residual std) for every day with a full window behind it
+    fn channel(
         &self,
-        actions: Vec<(NaiveDate, Action)>,
-        _: &BTreeMap<NaiveDate, DaySeriesData>,
-    ) -> Vec<(NaiveDate, Action)> {
+        trades: &BTreeMap<NaiveDate, DaySeriesData>,
+    ) -> Vec<(NaiveDate, Price, f64, f64)> {
+        let mut regression = MovingRegression::new(self.period);
         let mut result = Vec::new();
-        let mut max_buy_price = 0f64;
 
-        for action in actions {
-            match action.1 {
-                Action::Buy(price) => {
-                    max_buy_price = max_buy_price.max(price);
-                    result.push(action);
-                }
-                Action::Sell(price) => {
-                    if max_buy_price < price {
-                        result.push(action);
-                        max_buy_price = 0f64;
-                    }
-                }
+        for (date, data) in trades {
+            if regression.is_full() {
+                let line = regression.project(1).unwrap();
+                let std = regression.residual_std().unwrap();
+                result.push((*date, data.close, line, std));
             }
+
+            regression.push(data.close);
         }
 
         result
     }
 }
 
-#[derive(Debug)]
-pub struct StrategyEvaluatorConfig {
-    buy_factor: usize,
-    sell_factor: f64,
-    stoploss: Option<f64>,
-    show_steps: bool,
+impl BuySellStrategy for RegressionChannelStrategy {
+    fn buy(&self, trades: &BTreeMap<NaiveDate, DaySeriesData>) -> Vec<(NaiveDate, Action)> {
+        self.channel(trades)
+            .into_iter()
+            .filter(|(_, close, line, std)| *close < line - self.num_std * std)
+            .map(|(date, close, _, _)| (date, Action::Buy(close)))
+            .collect()
+    }
+
+    fn sell(&self, trades: &BTreeMap<NaiveDate, DaySeriesData>) -> Vec<(NaiveDate, Action)> {
+        self.channel(trades)
+            .into_iter()
+            .filter(|(_, close, line, std)| *close > line + self.num_std * std)
+            .map(|(date, close, _, _)| (date, Action::Sell(close)))
+            .collect()
+    }
 }
 
-impl Default for StrategyEvaluatorConfig {
-    fn default() -> Self {
-        Self {
-            buy_factor: 1,
-            sell_factor: 1.0,
-            stoploss: None,
-            show_steps: false,
-        }
+/// buy: 현재 주가가 trend_period 일 이동평균 위에 있으면서 pullback_period 일 이동평균 아래로 눌렸다
+/// sell: 현재 주가가 trend_period 일 이동평균 아래로 내려갔다
+pub struct TrendPullbackStrategy {
+    pub trend_period: usize,
+    pub pullback_period: usize,
+}
+
+impl TrendPullbackStrategy {
+    /// (date, close, trend average, pullback average) once both averages are available
+    fn averages(
+        &self,
+        trades: &BTreeMap<NaiveDate, DaySeriesData>,
+    ) -> Vec<(NaiveDate, Price, Price, Price)> {
+        let closes = trades.values().map(|d| d.close).collect_vec();
+        let trend = sma(&closes, self.trend_period);
+        let pullback = sma(&closes, self.pullback_period);
+
+        trades
+            .keys()
+            .zip(closes)
+            .zip(trend.into_iter().zip(pullback))
+            .filter_map(|((date, close), (trend, pullback))| {
+                Some((*date, close, trend?, pullback?))
+            })
+            .collect()
     }
 }
 
-impl StrategyEvaluatorConfig {
-    pub fn with_show_steps(mut self, value: bool) -> Self {
-        self.show_steps = value;
-        self
+impl BuySellStrategy for TrendPullbackStrategy {
+    fn buy(&self, trades: &BTreeMap<NaiveDate, DaySeriesData>) -> Vec<(NaiveDate, Action)> {
+        self.averages(trades)
+            .into_iter()
+            .filter(|(_, close, trend, pullback)| close > trend && close <= pullback)
+            .map(|(date, close, _, _)| (date, Action::Buy(close)))
+            .collect()
+    }
+
+    fn sell(&self, trades: &BTreeMap<NaiveDate, DaySeriesData>) -> Vec<(NaiveDate, Action)> {
+        self.averages(trades)
+            .into_iter()
+            .filter(|(_, close, trend, _)| close < trend)
+            .map(|(date, close, _, _)| (date, Action::Sell(close)))
+            .collect()
     }
 }
 
-pub struct StrategyEvaluator {
-    config: StrategyEvaluatorConfig,
+/// buy: 종가가 kama 위로 올라섰다
+/// sell: 종가가 kama 아래로 내려갔다
+pub struct KaufmanAmaStrategy {
+    pub period: usize,
+    pub fast: usize,
+    pub slow: usize,
 }
 
-#[derive(Debug, Copy, Clone)]
-pub struct StrategyEvaluatorResult {
-    stock: usize,
-    trading: usize,
-    balance: f64,
-    invest: f64,
-    income: f64,
-    roi: f64,
+/// (date, close, kama)
+type KamaBar = (NaiveDate, Price, Price);
+
+impl KaufmanAmaStrategy {
+    /// pairs of consecutive bars with a kama
+    fn crossings(&self, trades: &BTreeMap<NaiveDate, DaySeriesData>) -> Vec<(KamaBar, KamaBar)> {
+        let closes = trades.values().map(|d| d.close).collect_vec();
+        let kama = kama(&closes, self.period, self.fast, self.slow);
+
+        trades
+            .keys()
+            .zip(closes)
+            .zip(kama)
+            .filter_map(|((date, close), kama)| Some((*date, close, kama?)))
+            .tuple_windows()
+            .collect()
+    }
 }
 
-impl StrategyEvaluator {
-    pub fn evaluate<T>(
-        &self,
-        strategy: T,
-        folders: Vec<Box<dyn FoldStrategy>>,
-        trades: &BTreeMap<NaiveDate, DaySeriesData>,
-    ) -> StrategyEvaluatorResult
-    where
-        T: BuySellStrategy,
-    {
-        let actions = folders
+impl BuySellStrategy for KaufmanAmaStrategy {
+    fn buy(&self, trades: &BTreeMap<NaiveDate, DaySeriesData>) -> Vec<(NaiveDate, Action)> {
+        self.crossings(trades)
             .into_iter()
-            .fold(strategy.buy_sell(trades), |actions, folder| {
-                folder.fold(actions, &trades)
-            });
+            .filter(|((_, prev_close, prev_kama), (_, close, kama))| {
+                prev_close < prev_kama && close > kama
+            })
+            .map(|(_, (date, close, _))| (date, Action::Buy(close)))
+            .collect()
+    }
 
-        let (first_buy, _) = actions
-            .iter()
-            .find_position(|(_, act)| act.is_buy())
-            .unwrap();
+    fn sell(&self, trades: &BTreeMap<NaiveDate, DaySeriesData>) -> Vec<(NaiveDate, Action)> {
+        self.crossings(trades)
+            .into_iter()
+            .filter(|((_, prev_close, prev_kama), (_, close, kama))| {
+                prev_close > prev_kama && close < kama
+            })
+            .map(|(_, (date, close, _))| (date, Action::Sell(close)))
+            .collect()
+    }
+}
 
-        let mut stock = 0;
-        let mut trading = 0;
-        let mut balance = 0f64;
+/// buy: rsi가 oversold 아래에서 위로 올라섰다
+/// sell: rsi가 overbought 위에서 아래로 내려갔다
+pub struct RsiStrategy {
+    pub period: usize,
+    pub oversold: f64,
+    pub overbought: f64,
+}
 
-        let mut invest = 0f64;
-        let mut income = 0f64;
+/// (date, open, rsi)
+type RsiBar = (NaiveDate, Price, f64);
 
-        let mut avg = MovingAverage::default();
+impl RsiStrategy {
+    /// pairs of consecutive bars after the first `period` days
+    fn crossings(&self, trades: &BTreeMap<NaiveDate, DaySeriesData>) -> Vec<(RsiBar, RsiBar)> {
+        let closes = trades.values().map(|d| d.close).collect_vec();
 
-        let sells: BTreeSet<NaiveDate> = actions
+        trades
             .iter()
-            .filter(|(_, act)| act.is_sell())
-            .map(|(date, _)| *date)
-            .collect();
+            .zip(rsi(&closes, self.period))
+            .filter_map(|((date, data), rsi)| Some((*date, data.open, rsi?)))
+            .tuple_windows()
+            .collect()
+    }
+}
 
-        for (date, act) in actions.into_iter().skip(first_buy) {
-            // println!("{}", avg.avg());
-            match act {
-                Action::Buy(price) => {
-                    let buy_stock = self.config.buy_factor;
-                    invest += price * buy_stock as f64;
-                    balance -= price * buy_stock as f64;
-                    stock += buy_stock;
-                    trading += buy_stock;
-                    avg.feed(price, buy_stock);
-
-                    if self.config.show_steps {
-                        println!("{date} buy  {price}: {buy_stock}, {balance}");
-                    }
-                }
-                Action::Sell(price) => {
-                    if stock != 0 {
-                        let sell_stock = stock as f64 * self.config.sell_factor;
-                        income += price * sell_stock;
-                        balance += price * sell_stock;
-                        trading += sell_stock as usize;
-                        stock -= sell_stock as usize;
-                        avg.feed(-price, sell_stock as usize);
-
-                        if self.config.show_steps {
-                            println!("{date} sell {price}: {}, {balance}", sell_stock as usize);
-                        }
-                    }
-                }
-            }
+impl BuySellStrategy for RsiStrategy {
+    fn buy(&self, trades: &BTreeMap<NaiveDate, DaySeriesData>) -> Vec<(NaiveDate, Action)> {
+        self.crossings(trades)
+            .into_iter()
+            .filter(|((_, _, prev), (_, _, rsi))| *prev < self.oversold && *rsi >= self.oversold)
+            .map(|(_, (date, open, _))| (date, Action::Buy(open)))
+            .collect()
+    }
 
-            if let Some(stoploss) = self.config.stoploss {
-                let next_sell = sells.range((Included(&date), Unbounded)).next();
-                todo!();
-                // if avg.avg() < -stoploss {}
-            }
-        }
+    fn sell(&self, trades: &BTreeMap<NaiveDate, DaySeriesData>) -> Vec<(NaiveDate, Action)> {
+        self.crossings(trades)
+            .into_iter()
+            .filter(|((_, _, prev), (_, _, rsi))| {
+                *prev > self.overbought && *rsi <= self.overbought
+            })
+            .map(|(_, (date, open, _))| (date, Action::Sell(open)))
+            .collect()
+    }
+}
 
-        StrategyEvaluatorResult {
-            stock,
-            trading,
-            balance: balance + stock as f64 * trades.last_key_value().unwrap().1.close,
-            invest,
-            income,
-            roi: (income + stock as f64 * trades.last_key_value().unwrap().1.close) / invest,
+/// buy: macd가 signal 위로 올라섰다
+/// sell: macd가 signal 아래로 내려갔다
+pub struct MacdStrategy {
+    pub fast: usize,
+    pub slow: usize,
+    pub signal: usize,
+}
+
+impl Default for MacdStrategy {
+    fn default() -> Self {
+        Self {
+            fast: 12,
+            slow: 26,
+            signal: 9,
         }
     }
 }
 
-//
+/// (date, close, macd - signal)
+type MacdBar = (NaiveDate, Price, f64);
 
-pub struct Account {
-    balance: Price,
-    stocks: HashMap<String, usize>,
+impl MacdStrategy {
+    /// pairs of consecutive bars with a signal line
+    fn crossings(&self, trades: &BTreeMap<NaiveDate, DaySeriesData>) -> Vec<(MacdBar, MacdBar)> {
+        let closes = trades.values().map(|d| d.close).collect_vec();
+        let macd = ema(&closes, self.fast)
+            .into_iter()
+            .zip(ema(&closes, self.slow))
+            .map(|(fast, slow)| Some(fast? - slow?))
+            .collect_vec();
+
+        // the signal line starts with the first macd
+        let warmup = macd.iter().take_while(|macd| macd.is_none()).count();
+        let macd = macd.into_iter().flatten().collect_vec();
+        let signal = ema(&macd, self.signal);
+
+        trades
+            .iter()
+            .skip(warmup)
+            .zip(macd.iter().zip(signal))
+            .filter_map(|((date, data), (macd, signal))| Some((*date, data.close, macd - signal?)))
+            .tuple_windows()
+            .collect()
+    }
 }
 
-pub struct StockInfo<'a> {
-    code: String,
-    past_trades: &'a BTreeMap<NaiveDate, DaySeriesData>,
+impl BuySellStrategy for MacdStrategy {
+    fn buy(&self, trades: &BTreeMap<NaiveDate, DaySeriesData>) -> Vec<(NaiveDate, Action)> {
+        self.crossings(trades)
+            .into_iter()
+            .filter(|((_, _, prev), (_, _, histogram))| *prev <= 0.0 && *histogram > 0.0)
+            .map(|(_, (date, close, _))| (date, Action::Buy(close)))
+            .collect()
+    }
+
+    fn sell(&self, trades: &BTreeMap<NaiveDate, DaySeriesData>) -> Vec<(NaiveDate, Action)> {
+        self.crossings(trades)
+            .into_iter()
+            .filter(|((_, _, prev), (_, _, histogram))| *prev >= 0.0 && *histogram < 0.0)
+            .map(|(_, (date, close, _))| (date, Action::Sell(close)))
+            .collect()
+    }
 }
 
-pub trait LinearBuySellStrategy {
-    fn buy(&self, account: &Account, stock: &StockInfo) -> bool;
+/// buy: 종가가 볼린저 밴드 하단 아래로 내려갔다
+/// sell: 종가가 볼린저 밴드 상단 위로 올라갔다
+pub struct BollingerStrategy {
+    pub period: usize,
+    pub num_std: f64,
 }
 
-pub struct BackTester {}
+impl BollingerStrategy {
+    /// (date, close, lower, upper) of every bar from the `period`th on
+    fn bands(
+        &self,
+        trades: &BTreeMap<NaiveDate, DaySeriesData>,
+    ) -> Vec<(NaiveDate, Price, Price, Price)> {
+        let mut window = MovingStdDev::new(self.period);
 
-impl BackTester {}
+        trades
+            .iter()
+            .filter_map(|(date, data)| {
+                window.feed(data.close);
+                if !window.is_full() {
+                    return None;
+                }
 
-#[cfg(test)]
-mod tests {
-    use crate::{
-        loader::{KospiLoader, StockDataLoader},
-        strategy::{
-            ConsecutiveBuyRemover, FoldStrategy, LossSellRemover, NaiveStrategy, NeverSellStrategy,
-            StrategyEvaluator, StrategyEvaluatorConfig,
-        },
-    };
+                let (mean, width) = (window.mean(), self.num_std * window.std_dev());
+                Some((*date, data.close, mean - width, mean + width))
+            })
+            .collect()
+    }
+}
 
-    #[test]
-    fn unittest_naive_strategy() -> eyre::Result<()> {
-        let stocks = KospiLoader::load()?;
-        let stock = stocks.into_iter().find(|s| s.name == "SK하이닉스").unwrap();
+impl BuySellStrategy for BollingerStrategy {
+    fn buy(&self, trades: &BTreeMap<NaiveDate, DaySeriesData>) -> Vec<(NaiveDate, Action)> {
+        self.bands(trades)
+            .into_iter()
+            .filter(|(_, close, lower, _)| close < lower)
+            .map(|(date, close, _, _)| (date, Action::Buy(close)))
+            .collect()
+    }
 
-        println!("code: {}", stock.code);
+    fn sell(&self, trades: &BTreeMap<NaiveDate, DaySeriesData>) -> Vec<(NaiveDate, Action)> {
+        self.bands(trades)
+            .into_iter()
+            .filter(|(_, close, _, upper)| close > upper)
+            .map(|(date, close, _, _)| (date, Action::Sell(close)))
+            .collect()
+    }
+}
 
-        let strategy = NaiveStrategy {
-            buy_move: 20,
-            sell_move: 20,
-        };
+/// buy: 종가가 전날 종가보다 전날 ATR의 multiple배 이상 높다
+/// sell: 종가가 전날 종가보다 전날 ATR의 multiple배 이상 낮다
+pub struct AtrStopStrategy {
+    pub atr_period: usize,
+    pub multiple: f64,
+}
 
-        let folder = vec![
-            ConsecutiveBuyRemover {}.boxed(),
-            LossSellRemover {}.boxed(),
-            // NeverSellStrategy {}.boxed(),
-        ];
+impl AtrStopStrategy {
+    /// (date, close, lower, upper) of every bar after the first `atr_period`, the band is the
+    /// previous close widened by `multiple` times the atr up to the previous bar
+    fn bands(
+        &self,
+        trades: &BTreeMap<NaiveDate, DaySeriesData>,
+    ) -> Vec<(NaiveDate, Price, Price, Price)> {
+        let bars = trades.values().copied().collect::<Vec<_>>();
 
-        let r = StrategyEvaluator {
-            config: StrategyEvaluatorConfig::default().with_show_steps(true),
-        }
-        .evaluate(strategy, folder, &stock.trades);
+        trades
+            .iter()
+            .zip(bars.iter().zip(atr(&bars, self.atr_period)))
+            .tuple_windows()
+            .filter_map(|((_, (prev, atr)), ((date, data), _))| {
+                let width = self.multiple * atr?;
+                Some((*date, data.close, prev.close - width, prev.close + width))
+            })
+            .collect()
+    }
+}
 
-        println!("result: {r:#?}");
+impl BuySellStrategy for AtrStopStrategy {
+    fn buy(&self, trades: &BTreeMap<NaiveDate, DaySeriesData>) -> Vec<(NaiveDate, Action)> {
+        self.bands(trades)
+            .into_iter()
+            .filter(|(_, close, _, upper)| close > upper)
+            .map(|(date, close, _, _)| (date, Action::Buy(close)))
+            .collect()
+    }
+
+    fn sell(&self, trades: &BTreeMap<NaiveDate, DaySeriesData>) -> Vec<(NaiveDate, Action)> {
+        self.bands(trades)
+            .into_iter()
+            .filter(|(_, close, lower, _)| close < lower)
+            .map(|(date, close, _, _)| (date, Action::Sell(close)))
+            .collect()
+    }
+}
+
+/// buy: 거래량이 직전 lookback일 평균의 multiple배를 넘고 종가가 올랐다
+/// sell: 거래량이 직전 lookback일 평균의 multiple배를 넘고 종가가 내렸다
+pub struct VolumeSpikeStrategy {
+    pub lookback: usize,
+    pub multiple: f64,
+}
+
+impl VolumeSpikeStrategy {
+    /// (date, close, close change) of every bar whose volume exceeds `multiple` times the
+    /// average volume of the `lookback` bars before it
+    fn spikes(&self, trades: &BTreeMap<NaiveDate, DaySeriesData>) -> Vec<(NaiveDate, Price, f64)> {
+        let mut volume = MovingAverage::new(self.lookback);
+        let mut result = Vec::new();
+        let mut prev_close = None;
+
+        for (date, data) in trades {
+            if let Some(prev_close) = prev_close {
+                if volume.len() == self.lookback
+                    && data.volume as f64 > self.multiple * volume.avg()
+                {
+                    result.push((*date, data.close, data.close - prev_close));
+                }
+            }
+            volume.feed(data.volume as f64, 1);
+            prev_close = Some(data.close);
+        }
+
+        result
+    }
+}
+
+impl BuySellStrategy for VolumeSpikeStrategy {
+    fn buy(&self, trades: &BTreeMap<NaiveDate, DaySeriesData>) -> Vec<(NaiveDate, Action)> {
+        self.spikes(trades)
+            .into_iter()
+            .filter(|(_, _, change)| *change > 0.0)
+            .map(|(date, close, _)| (date, Action::Buy(close)))
+            .collect()
+    }
+
+    fn sell(&self, trades: &BTreeMap<NaiveDate, DaySeriesData>) -> Vec<(NaiveDate, Action)> {
+        self.spikes(trades)
+            .into_iter()
+            .filter(|(_, _, change)| *change < 0.0)
+            .map(|(date, close, _)| (date, Action::Sell(close)))
+            .collect()
+    }
+}
+
+/// buy: 종가의 직전 window일 z-score가 -entry 아래로 내려갔다
+/// sell: 종가의 직전 window일 z-score가 exit 위로 올라갔다
+pub struct ZScoreStrategy {
+    pub window: usize,
+    pub entry: f64,
+    pub exit: f64,
+}
+
+impl ZScoreStrategy {
+    /// (date, close, z-score) of every bar after the first `window`, the close's distance from
+    /// the mean of the `window` closes before it in sample deviations of them
+    fn z_scores(
+        &self,
+        trades: &BTreeMap<NaiveDate, DaySeriesData>,
+    ) -> Vec<(NaiveDate, Price, f64)> {
+        let mut window = MovingStdDev::new(self.window);
+        let mut result = Vec::new();
+
+        for (date, data) in trades {
+            let std = window.std();
+            if window.is_full() && std > 0.0 {
+                result.push((*date, data.close, (data.close - window.mean()) / std));
+            }
+            window.feed(data.close);
+        }
+
+        result
+    }
+}
+
+impl BuySellStrategy for ZScoreStrategy {
+    fn buy(&self, trades: &BTreeMap<NaiveDate, DaySeriesData>) -> Vec<(NaiveDate, Action)> {
+        self.z_scores(trades)
+            .into_iter()
+            .filter(|(_, _, z)| *z < -self.entry)
+            .map(|(date, close, _)| (date, Action::Buy(close)))
+            .collect()
+    }
+
+    fn sell(&self, trades: &BTreeMap<NaiveDate, DaySeriesData>) -> Vec<(NaiveDate, Action)> {
+        self.z_scores(trades)
+            .into_iter()
+            .filter(|(_, _, z)| *z > self.exit)
+            .map(|(date, close, _)| (date, Action::Sell(close)))
+            .collect()
+    }
+}
+
+/// buy: 종가가 전날 고가보다 range_pct 이상 높다
+/// sell: 종가가 전날 저가보다 낮다
+pub struct OpeningRangeBreakoutStrategy {
+    pub range_pct: f64,
+}
+
+impl BuySellStrategy for OpeningRangeBreakoutStrategy {
+    fn buy(&self, trades: &BTreeMap<NaiveDate, DaySeriesData>) -> Vec<(NaiveDate, Action)> {
+        trades
+            .iter()
+            .tuple_windows()
+            .filter(|((_, prev), (_, data))| data.close > prev.high * (1.0 + self.range_pct))
+            .map(|(_, (date, data))| (*date, Action::Buy(data.close)))
+            .collect()
+    }
+
+    fn sell(&self, trades: &BTreeMap<NaiveDate, DaySeriesData>) -> Vec<(NaiveDate, Action)> {
+        trades
+            .iter()
+            .tuple_windows()
+            .filter(|((_, prev), (_, data))| data.close < prev.low)
+            .map(|(_, (date, data))| (*date, Action::Sell(data.close)))
+            .collect()
+    }
+}
+
+/// buy: 전날 rise % 만큼 을랐다
+/// sell: 없음
+pub struct BeginningSurpriseStrategy {
+    pub rise: f64,
+}
+
+impl BuySellStrategy for BeginningSurpriseStrategy {
+    fn buy(&self, trades: &BTreeMap<NaiveDate, DaySeriesData>) -> Vec<(NaiveDate, Action)> {
+        trades
+            .iter()
+            .tuple_windows()
+            .filter(|((_, before), (_, prev), _)| prev.close / before.close - 1.0 >= self.rise)
+            .map(|(_, _, (date, data))| (*date, Action::Buy(data.open)))
+            .collect()
+    }
+
+    fn sell(&self, _: &BTreeMap<NaiveDate, DaySeriesData>) -> Vec<(NaiveDate, Action)> {
+        Vec::new()
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CombineMode {
+    /// dates every child signals on
+    All,
+    /// dates any child signals on
+    Any,
+}
+
+/// buy: 자식 전략이 모두(All) 또는 하나라도(Any) 매수한다
+/// sell: 자식 전략이 모두(All) 또는 하나라도(Any) 매도한다
+pub struct CompositeStrategy {
+    pub children: Vec<Box<dyn BuySellStrategy>>,
+    pub mode: CombineMode,
+}
+
+impl CompositeStrategy {
+    /// one action per combined date, the action of the first child signaling on it
+    fn combine(&self, signals: Vec<Vec<(NaiveDate, Action)>>) -> Vec<(NaiveDate, Action)> {
+        let dates = signals
+            .iter()
+            .map(|actions| {
+                actions
+                    .iter()
+                    .map(|(date, _)| *date)
+                    .collect::<BTreeSet<_>>()
+            })
+            .collect_vec();
+
+        let mut result: BTreeMap<NaiveDate, Action> = BTreeMap::new();
+        for (date, act) in signals.into_iter().flatten() {
+            result.entry(date).or_insert(act);
+        }
+        if let CombineMode::All = self.mode {
+            result.retain(|date, _| dates.iter().all(|dates| dates.contains(date)));
+        }
+
+        result.into_iter().collect()
+    }
+}
+
+impl BuySellStrategy for CompositeStrategy {
+    fn buy(&self, trades: &BTreeMap<NaiveDate, DaySeriesData>) -> Vec<(NaiveDate, Action)> {
+        self.combine(
+            self.children
+                .iter()
+                .map(|child| child.buy(trades))
+                .collect(),
+        )
+    }
+
+    fn sell(&self, trades: &BTreeMap<NaiveDate, DaySeriesData>) -> Vec<(NaiveDate, Action)> {
+        self.combine(
+            self.children
+                .iter()
+                .map(|child| child.sell(trades))
+                .collect(),
+        )
+    }
+}
+
+/// where a fold runs in a `FoldPipeline`, earlier stages first
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum FoldStage {
+    /// rewrites the signals themselves
+    Transform,
+    /// injects exits into the position the signals build
+    Exit,
+    /// thins out runs of repeated actions
+    Dedup,
+    /// drops actions depending on the simulated position and its pnl, after the runs are
+    /// thinned so every kept buy counts once
+    Risk,
+}
+
+pub trait FoldStrategy
+where
+    Self: 'static,
+{
+    fn fold(
+        &self,
+        actions: Vec<(NaiveDate, Action)>,
+        trades: &BTreeMap<NaiveDate, DaySeriesData>,
+    ) -> Vec<(NaiveDate, Action)>;
+
+    fn stage(&self) -> FoldStage {
+        FoldStage::Transform
+    }
+
+    fn boxed(self) -> Box<dyn FoldStrategy>
+    where
+        Self: Sized,
+    {
+        Box::new(self)
+    }
+}
+
+pub struct NeverSellStrategy {}
+
+impl FoldStrategy for NeverSellStrategy {
+    fn stage(&self) -> FoldStage {
+        FoldStage::Dedup
+    }
+
+    fn fold(
+        &self,
+        actions: Vec<(NaiveDate, Action)>,
+        _: &BTreeMap<NaiveDate, DaySeriesData>,
+    ) -> Vec<(NaiveDate, Action)> {
+        actions
+            .into_iter()
+            .filter(|(_, act)| !act.is_sell())
+            .collect()
+    }
+}
+
+pub struct ConsecutiveBuyRemover {}
+
+impl FoldStrategy for ConsecutiveBuyRemover {
+    fn stage(&self) -> FoldStage {
+        FoldStage::Dedup
+    }
+
+    fn fold(
+        &self,
+        actions: Vec<(NaiveDate, Action)>,
+        _: &BTreeMap<NaiveDate, DaySeriesData>,
+    ) -> Vec<(NaiveDate, Action)> {
+        actions
+            .into_iter()
+            .dedup_by(|(_, lact), (_, ract)| mem::discriminant(lact) == mem::discriminant(ract))
+            .collect()
+    }
+}
+
+/// keeps only the last sell of every run of sells without a buy in between, exiting as late
+/// as the run allows. buys pass through
+pub struct ConsecutiveSellRemover {}
+
+impl FoldStrategy for ConsecutiveSellRemover {
+    fn stage(&self) -> FoldStage {
+        FoldStage::Dedup
+    }
+
+    fn fold(
+        &self,
+        actions: Vec<(NaiveDate, Action)>,
+        _: &BTreeMap<NaiveDate, DaySeriesData>,
+    ) -> Vec<(NaiveDate, Action)> {
+        actions
+            .into_iter()
+            .coalesce(|prev, next| {
+                if prev.1.is_sell() && next.1.is_sell() {
+                    Ok(next)
+                } else {
+                    Err((prev, next))
+                }
+            })
+            .collect()
+    }
+}
+
+/// drops buys fewer than `min_days` calendar days after the last kept buy, other actions pass
+/// through
+pub struct CooldownFold {
+    pub min_days: i64,
+}
+
+impl FoldStrategy for CooldownFold {
+    fn stage(&self) -> FoldStage {
+        FoldStage::Dedup
+    }
+
+    fn fold(
+        &self,
+        actions: Vec<(NaiveDate, Action)>,
+        _: &BTreeMap<NaiveDate, DaySeriesData>,
+    ) -> Vec<(NaiveDate, Action)> {
+        let mut last_buy: Option<NaiveDate> = None;
+
+        actions
+            .into_iter()
+            .filter(|(date, act)| {
+                if !act.is_buy() {
+                    return true;
+                }
+                if last_buy.is_some_and(|last| (*date - last).num_days() < self.min_days) {
+                    return false;
+                }
+                last_buy = Some(*date);
+                true
+            })
+            .collect()
+    }
+}
+
+/// injects a sell at the close that falls `pct` below the highest close since the last buy,
+/// unlike the config's stoploss the stop ratchets up with the gains
+pub struct TrailingStopFold {
+    pub pct: f64,
+}
+
+impl FoldStrategy for TrailingStopFold {
+    fn stage(&self) -> FoldStage {
+        FoldStage::Exit
+    }
+
+    fn fold(
+        &self,
+        actions: Vec<(NaiveDate, Action)>,
+        trades: &BTreeMap<NaiveDate, DaySeriesData>,
+    ) -> Vec<(NaiveDate, Action)> {
+        let mut actions = actions.into_iter().peekable();
+        let mut result = Vec::new();
+        // highest price since the last buy while holding
+        let mut peak: Option<Price> = None;
+
+        for (today, data) in trades {
+            while let Some((date, act)) = actions.next_if(|(date, _)| date <= today) {
+                peak = match act {
+                    Action::Buy(price) => Some(price),
+                    Action::Sell(_) => None,
+                    Action::Short(_) | Action::Cover(_) => peak,
+                };
+                result.push((date, act));
+            }
+
+            if let Some(high) = peak {
+                let high = high.max(data.close);
+                if data.close <= high * (1.0 - self.pct) {
+                    result.push((*today, Action::Sell(data.close)));
+                    peak = None;
+                } else {
+                    peak = Some(high);
+                }
+            }
+        }
+
+        result.extend(actions);
+        result
+    }
+}
+
+/// injects a sell at the first close more than `pct` above the average buy price of the
+/// position, then waits for the next buy
+pub struct TakeProfitFold {
+    pub pct: f64,
+}
+
+impl FoldStrategy for TakeProfitFold {
+    fn stage(&self) -> FoldStage {
+        FoldStage::Exit
+    }
+
+    fn fold(
+        &self,
+        actions: Vec<(NaiveDate, Action)>,
+        trades: &BTreeMap<NaiveDate, DaySeriesData>,
+    ) -> Vec<(NaiveDate, Action)> {
+        let mut actions = actions.into_iter().peekable();
+        let mut result = Vec::new();
+        let mut cost_basis = MovingAverage::new(usize::MAX);
+
+        for (today, data) in trades {
+            while let Some((date, act)) = actions.next_if(|(date, _)| date <= today) {
+                match act {
+                    Action::Buy(price) => cost_basis.feed(price, 1),
+                    Action::Sell(_) => cost_basis.clear(),
+                    Action::Short(_) | Action::Cover(_) => {}
+                }
+                result.push((date, act));
+            }
+
+            if !cost_basis.is_empty() && data.close / cost_basis.avg() - 1.0 > self.pct {
+                result.push((*today, Action::Sell(data.close)));
+                cost_basis.clear();
+            }
+        }
+
+        result.extend(actions);
+        result
+    }
+}
+
+/// drops sells at or below the average price of the buys since the last kept sell, a kept
+/// sell exits the whole position. shorts and covers pass through
+pub struct LossSellRemover {}
+
+impl FoldStrategy for LossSellRemover {
+    fn stage(&self) -> FoldStage {
+        FoldStage::Risk
+    }
+
+    fn fold(
+        &self,
+        actions: Vec<(NaiveDate, Action)>,
+        _: &BTreeMap<NaiveDate, DaySeriesData>,
+    ) -> Vec<(NaiveDate, Action)> {
+        let mut result = Vec::new();
+        let mut cost_basis = MovingAverage::new(usize::MAX);
+
+        for action in actions {
+            match action.1 {
+                Action::Buy(price) => {
+                    cost_basis.feed(price, 1);
+                    result.push(action);
+                }
+                Action::Sell(price) => {
+                    if cost_basis.is_empty() || cost_basis.avg() < price {
+                        result.push(action);
+                        cost_basis.clear();
+                    }
+                }
+                Action::Short(_) | Action::Cover(_) => result.push(action),
+            }
+        }
+
+        result
+    }
+}
+
+/// turns every buy into a sell at the same price and vice versa, e.g. momentum into contrarian,
+/// and every short into a cover
+pub struct InvertFold {}
+
+impl FoldStrategy for InvertFold {
+    fn fold(
+        &self,
+        actions: Vec<(NaiveDate, Action)>,
+        _: &BTreeMap<NaiveDate, DaySeriesData>,
+    ) -> Vec<(NaiveDate, Action)> {
+        actions
+            .into_iter()
+            .map(|(date, act)| match act {
+                Action::Buy(price) => (date, Action::Sell(price)),
+                Action::Sell(price) => (date, Action::Buy(price)),
+                Action::Short(price) => (date, Action::Cover(price)),
+                Action::Cover(price) => (date, Action::Short(price)),
+            })
+            .collect()
+    }
+}
+
+/// drops the buys of the rest of a day and of the next trading day once that day's realized
+/// and unrealized loss exceeds `limit_pct` of the equity at its start, the loss is marked at
+/// every fill and at the close of `trades`. the position is simulated like the evaluator's
+/// defaults, one share per buy and everything per sell, on an account sized by its largest
+/// outlay. shorts and covers pass through without being simulated
+pub struct DailyLossLimitFold {
+    pub limit_pct: f64,
+}
+
+impl FoldStrategy for DailyLossLimitFold {
+    fn stage(&self) -> FoldStage {
+        FoldStage::Risk
+    }
+
+    fn fold(
+        &self,
+        actions: Vec<(NaiveDate, Action)>,
+        trades: &BTreeMap<NaiveDate, DaySeriesData>,
+    ) -> Vec<(NaiveDate, Action)> {
+        let dates = trades
+            .keys()
+            .chain(actions.iter().map(|(date, _)| date))
+            .copied()
+            .collect::<BTreeSet<_>>();
+        let mut actions = actions.into_iter().peekable();
+        let mut result = Vec::new();
+        let (mut shares, mut cash, mut capital) = (0usize, 0f64, 0f64);
+        // last close or fill price the position is valued at
+        let mut mark = 0.0;
+        // the previous day lost more than the limit
+        let mut halted = false;
+
+        for date in dates {
+            let start_value = cash + shares as f64 * mark;
+            let start_equity = capital + start_value;
+            let exceeded = |value: f64| {
+                start_equity > 0.0 && start_value - value > self.limit_pct * start_equity
+            };
+            let mut lost = false;
+
+            while let Some((date, act)) = actions.next_if(|(day, _)| *day == date) {
+                match act {
+                    Action::Buy(_) if halted || lost => continue,
+                    Action::Buy(price) => {
+                        shares += 1;
+                        cash -= price;
+                        capital = capital.max(-cash);
+                    }
+                    Action::Sell(price) => {
+                        cash += shares as f64 * price;
+                        shares = 0;
+                    }
+                    Action::Short(_) | Action::Cover(_) => {}
+                }
+                result.push((date, act));
+
+                mark = act.price();
+                lost |= exceeded(cash + shares as f64 * mark);
+            }
+
+            if let Some(data) = trades.get(&date) {
+                mark = data.close;
+                lost |= exceeded(cash + shares as f64 * mark);
+            }
+            halted = lost;
+        }
+
+        result
+    }
+}
+
+/// folds kept in `FoldStage` order whatever order they are added in, folds of the same stage
+/// run in the order they were added
+#[derive(Default)]
+pub struct FoldPipeline {
+    folds: Vec<Box<dyn FoldStrategy>>,
+}
+
+impl FoldPipeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// adds `fold` after the folds of its stage and the ones before
+    pub fn then(mut self, fold: impl FoldStrategy) -> Self {
+        let ix = self
+            .folds
+            .iter()
+            .position(|other| other.stage() > fold.stage())
+            .unwrap_or(self.folds.len());
+        self.folds.insert(ix, fold.boxed());
+        self
+    }
+
+    /// the folds in the order they run, as `StrategyEvaluator::evaluate` takes them
+    pub fn build(self) -> Vec<Box<dyn FoldStrategy>> {
+        self.folds
+    }
+
+    pub fn fold_all(
+        &self,
+        actions: Vec<(NaiveDate, Action)>,
+        trades: &BTreeMap<NaiveDate, DaySeriesData>,
+    ) -> Vec<(NaiveDate, Action)> {
+        self.folds
+            .iter()
+            .fold(actions, |actions, folder| folder.fold(actions, trades))
+    }
+}
+
+/// desired fraction of equity held in the stock, from each date until the next one
+pub trait TargetWeightStrategy {
+    fn weights(&self, trades: &BTreeMap<NaiveDate, DaySeriesData>) -> Vec<(NaiveDate, f64)>;
+}
+
+/// fully invested after a buy signal, flat after a sell signal
+pub struct SignalWeights<T: BuySellStrategy>(pub T);
+
+impl<T: BuySellStrategy> TargetWeightStrategy for SignalWeights<T> {
+    fn weights(&self, trades: &BTreeMap<NaiveDate, DaySeriesData>) -> Vec<(NaiveDate, f64)> {
+        self.0
+            .buy_sell(trades)
+            .into_iter()
+            .map(|(date, act)| (date, if act.is_buy() { 1.0 } else { 0.0 }))
+            .collect()
+    }
+}
+
+/// sum of every member's latest weight scaled by its blend weight,
+/// negative totals are evaluated as flat
+pub struct WeightedBlendStrategy {
+    pub members: Vec<(f64, Box<dyn TargetWeightStrategy>)>,
+}
+
+impl TargetWeightStrategy for WeightedBlendStrategy {
+    fn weights(&self, trades: &BTreeMap<NaiveDate, DaySeriesData>) -> Vec<(NaiveDate, f64)> {
+        let members = self
+            .members
+            .iter()
+            .map(|(blend, member)| {
+                let weights: BTreeMap<_, _> = member.weights(trades).into_iter().collect();
+                (*blend, weights)
+            })
+            .collect_vec();
+
+        let dates: BTreeSet<NaiveDate> = members
+            .iter()
+            .flat_map(|(_, weights)| weights.keys().copied())
+            .collect();
+
+        dates
+            .into_iter()
+            .map(|date| {
+                let weight = members
+                    .iter()
+                    .filter_map(|(blend, weights)| {
+                        weights
+                            .range(..=date)
+                            .next_back()
+                            .map(|(_, weight)| blend * weight)
+                    })
+                    .sum();
+                (date, weight)
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub enum BuySizing {
+    /// fixed number of shares per buy
+    Shares(usize),
+    /// fraction of the settled cash per buy
+    CashPercent(f64),
+    /// fixed cash amount per buy, `cash / price` fractional shares
+    Cash(f64),
+}
+
+#[derive(Debug, Default, Copy, Clone, Serialize, Deserialize)]
+pub enum ExecutionModel {
+    /// fills the whole order at the price carried by the action
+    #[default]
+    Signal,
+    /// fills at the bar's typical price `(high + low + close) / 3`, at most
+    /// `participation * volume` shares
+    Vwap { participation: f64 },
+    /// buys and covers fill at the bar's high, sells and shorts at its low
+    Pessimistic,
+}
+
+impl ExecutionModel {
+    /// (fill price, filled shares) of an order for `shares` on `bar`
+    pub fn fill(&self, bar: &DaySeriesData, action: Action, shares: Shares) -> (Price, Shares) {
+        match (self, action) {
+            (ExecutionModel::Signal, _) => (action.price(), shares),
+            (ExecutionModel::Pessimistic, Action::Buy(_) | Action::Cover(_)) => (bar.high, shares),
+            (ExecutionModel::Pessimistic, Action::Sell(_) | Action::Short(_)) => (bar.low, shares),
+            (ExecutionModel::Vwap { participation }, _) => (
+                (bar.high + bar.low + bar.close) / 3.0,
+                shares.min((participation * bar.volume as f64).floor()),
+            ),
+        }
+    }
+}
+
+type FillPrice = dyn Fn(&DaySeriesData, &Action) -> Price;
+
+/// caller supplied fill price of an action on its bar, shared so configs stay cloneable
+#[derive(Clone)]
+pub struct FillFn(Arc<FillPrice>);
+
+impl std::fmt::Debug for FillFn {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("FillFn")
+    }
+}
+
+/// how actions of the same kind on the same date, e.g. exits injected by several folds,
+/// are resolved before evaluation
+#[derive(Debug, Default, Copy, Clone, Serialize, Deserialize)]
+pub enum SameDayPolicy {
+    /// the most conservative fill wins, the highest buy or cover and the lowest sell or short
+    #[default]
+    WorstPrice,
+    /// the action produced first by the pipeline wins
+    First,
+    /// every action is executed
+    KeepAll,
+}
+
+/// sorts `actions` by date and keeps one action of each kind per date according to `policy`,
+/// the order of a single date is the order the kinds first appeared in
+pub fn resolve_same_day(
+    actions: Vec<(NaiveDate, Action)>,
+    policy: SameDayPolicy,
+) -> Vec<(NaiveDate, Action)> {
+    let mut actions = actions;
+    actions.sort_by_key(|(date, _)| *date);
+
+    if let SameDayPolicy::KeepAll = policy {
+        return actions;
+    }
+
+    let mut result: Vec<(NaiveDate, Action)> = Vec::new();
+    let mut slots: HashMap<(NaiveDate, mem::Discriminant<Action>), usize> = HashMap::new();
+
+    for (date, act) in actions {
+        let kind = mem::discriminant(&act);
+        let Some(&slot) = slots.get(&(date, kind)) else {
+            slots.insert((date, kind), result.len());
+            result.push((date, act));
+            continue;
+        };
+
+        if let SameDayPolicy::WorstPrice = policy {
+            let kept = &mut result[slot].1;
+            *kept = match (*kept, act) {
+                (Action::Buy(l), Action::Buy(r)) => Action::Buy(l.max(r)),
+                (Action::Sell(l), Action::Sell(r)) => Action::Sell(l.min(r)),
+                (Action::Short(l), Action::Short(r)) => Action::Short(l.min(r)),
+                (Action::Cover(l), Action::Cover(r)) => Action::Cover(l.max(r)),
+                _ => unreachable!(),
+            };
+        }
+    }
+
+    result
+}
+
+/// fee = max(per_share * shares + rate * price * shares, min)
+#[derive(Debug, Default, Copy, Clone, Serialize, Deserialize)]
+pub struct CommissionModel {
+    pub rate: f64,
+    pub min: f64,
+    pub per_share: f64,
+}
+
+impl CommissionModel {
+    pub fn percentage(rate: f64) -> Self {
+        Self {
+            rate,
+            ..Default::default()
+        }
+    }
+
+    pub fn per_share(per_share: f64) -> Self {
+        Self {
+            per_share,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_min(mut self, value: f64) -> Self {
+        self.min = value;
+        self
+    }
+
+    pub fn fee(&self, price: Price, shares: Shares) -> f64 {
+        if shares == 0.0 {
+            return 0.0;
+        }
+
+        (self.per_share * shares + self.rate * price * shares).max(self.min)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StrategyEvaluatorConfig {
+    buy_sizing: BuySizing,
+    sell_factor: f64,
+    /// loss below the average cost at which the position is sold at the close, e.g. 0.1 sells
+    /// once a close is 10% under it
+    stoploss: Option<f64>,
+    show_steps: bool,
+    /// starting cash, equity is `balance + stock * price`
+    initial_capital: f64,
+    /// upper bound of `gross_exposure / equity`, buys are scaled down to stay below it
+    #[serde(with = "lossless_float")]
+    max_leverage: f64,
+    /// cash dividend per share, credited to holders on the date
+    dividends: BTreeMap<NaiveDate, Price>,
+    /// trading days until credited cash can be spent on buys
+    cash_settlement_days: usize,
+    commission: CommissionModel,
+    /// inclusive date ranges in which buys are skipped, sells still execute
+    blackout_ranges: Vec<(NaiveDate, NaiveDate)>,
+    /// position held before the first signal, counted as invested at its cost basis
+    initial_shares: usize,
+    initial_cost_basis: Price,
+    /// panics on fills outside of the bar's `[low, high]`, meant to catch broken folds
+    validate_fills: bool,
+    /// (max bps, seed), every fill moves against the trade by a random amount up to the cap
+    random_slippage: Option<(f64, u64)>,
+    execution: ExecutionModel,
+    same_day_policy: SameDayPolicy,
+    /// cash deposited on the date, or on the next trading day
+    contributions: Vec<(NaiveDate, f64)>,
+    /// cash deposited on the first trading day of every month after the first
+    monthly_contribution: f64,
+    /// sharpe, cagr and max drawdown from the log equity instead of the equity
+    log_metrics: bool,
+    /// daily rate subtracted from every daily return before the sharpe ratio
+    risk_free_rate: f64,
+    /// executions after which the backtest stops early
+    max_trades: Option<usize>,
+    /// actions of the pipeline ignored before the evaluation begins
+    skip_first_n_signals: usize,
+    /// overrides the fill price of the execution model, not serialized
+    #[serde(skip)]
+    fill_fn: Option<FillFn>,
+    /// sells the remaining position at the close of the last evaluated day
+    liquidate_at_end: bool,
+    /// keeps every round trip in the result's `trades_log`
+    trade_log: bool,
+    /// metric `compare_strategies` ranks by, best first
+    rank_metric: RankMetric,
+}
+
+impl Default for StrategyEvaluatorConfig {
+    fn default() -> Self {
+        Self {
+            buy_sizing: BuySizing::Shares(1),
+            sell_factor: 1.0,
+            stoploss: None,
+            show_steps: false,
+            initial_capital: 0.0,
+            max_leverage: f64::INFINITY,
+            dividends: BTreeMap::new(),
+            cash_settlement_days: 0,
+            commission: CommissionModel::default(),
+            blackout_ranges: Vec::new(),
+            initial_shares: 0,
+            initial_cost_basis: 0.0,
+            validate_fills: false,
+            random_slippage: None,
+            execution: ExecutionModel::Signal,
+            same_day_policy: SameDayPolicy::WorstPrice,
+            contributions: Vec::new(),
+            monthly_contribution: 0.0,
+            log_metrics: false,
+            risk_free_rate: 0.0,
+            max_trades: None,
+            skip_first_n_signals: 0,
+            fill_fn: None,
+            liquidate_at_end: false,
+            trade_log: false,
+            rank_metric: RankMetric::Roi,
+        }
+    }
+}
+
+impl StrategyEvaluatorConfig {
+    pub fn with_show_steps(mut self, value: bool) -> Self {
+        self.show_steps = value;
+        self
+    }
+
+    pub fn with_buy_sizing(mut self, value: BuySizing) -> Self {
+        self.buy_sizing = value;
+        self
+    }
+
+    pub fn with_sell_factor(mut self, value: f64) -> Self {
+        self.sell_factor = value;
+        self
+    }
+
+    pub fn with_stoploss(mut self, value: f64) -> Self {
+        self.stoploss = Some(value);
+        self
+    }
+
+    pub fn with_initial_capital(mut self, value: f64) -> Self {
+        self.initial_capital = value;
+        self
+    }
+
+    pub fn with_max_leverage(mut self, value: f64) -> Self {
+        self.max_leverage = value;
+        self
+    }
+
+    pub fn with_dividends(mut self, value: BTreeMap<NaiveDate, Price>) -> Self {
+        self.dividends = value;
+        self
+    }
+
+    pub fn with_cash_settlement_days(mut self, value: usize) -> Self {
+        self.cash_settlement_days = value;
+        self
+    }
+
+    pub fn with_commission(mut self, value: CommissionModel) -> Self {
+        self.commission = value;
+        self
+    }
+
+    /// sets the `rate` of the commission model, on top of any per share fee
+    pub fn with_commission_rate(mut self, value: f64) -> Self {
+        self.commission.rate = value;
+        self
+    }
+
+    /// sets the `min` of the commission model
+    pub fn with_commission_min(mut self, value: f64) -> Self {
+        self.commission.min = value;
+        self
+    }
+
+    pub fn with_blackout_ranges(mut self, value: Vec<(NaiveDate, NaiveDate)>) -> Self {
+        self.blackout_ranges = value;
+        self
+    }
+
+    pub fn with_initial_position(mut self, shares: usize, cost_basis: Price) -> Self {
+        self.initial_shares = shares;
+        self.initial_cost_basis = cost_basis;
+        self
+    }
+
+    pub fn with_validate_fills(mut self, value: bool) -> Self {
+        self.validate_fills = value;
+        self
+    }
+
+    pub fn with_random_slippage(mut self, max_bps: f64, seed: u64) -> Self {
+        self.random_slippage = Some((max_bps, seed));
+        self
+    }
+
+    pub fn with_execution(mut self, value: ExecutionModel) -> Self {
+        self.execution = value;
+        self
+    }
+
+    pub fn with_same_day_policy(mut self, value: SameDayPolicy) -> Self {
+        self.same_day_policy = value;
+        self
+    }
+
+    pub fn with_contributions(mut self, value: Vec<(NaiveDate, f64)>) -> Self {
+        self.contributions = value;
+        self
+    }
+
+    pub fn with_monthly_contribution(mut self, value: f64) -> Self {
+        self.monthly_contribution = value;
+        self
+    }
+
+    pub fn with_log_metrics(mut self, value: bool) -> Self {
+        self.log_metrics = value;
+        self
+    }
+
+    pub fn with_risk_free_rate(mut self, value: f64) -> Self {
+        self.risk_free_rate = value;
+        self
+    }
+
+    pub fn with_max_trades(mut self, value: usize) -> Self {
+        self.max_trades = Some(value);
+        self
+    }
+
+    pub fn with_liquidate_at_end(mut self, value: bool) -> Self {
+        self.liquidate_at_end = value;
+        self
+    }
+
+    pub fn with_trade_log(mut self, value: bool) -> Self {
+        self.trade_log = value;
+        self
+    }
+
+    pub fn with_rank_metric(mut self, value: RankMetric) -> Self {
+        self.rank_metric = value;
+        self
+    }
+
+    pub fn with_skip_first_n_signals(mut self, value: usize) -> Self {
+        self.skip_first_n_signals = value;
+        self
+    }
+
+    pub fn with_fill_fn<F>(mut self, value: F) -> Self
+    where
+        F: Fn(&DaySeriesData, &Action) -> Price + 'static,
+    {
+        self.fill_fn = Some(FillFn(Arc::new(value)));
+        self
+    }
+
+    fn in_blackout(&self, date: NaiveDate) -> bool {
+        self.blackout_ranges
+            .iter()
+            .any(|(start, end)| (*start..=*end).contains(&date))
+    }
+}
+
+pub struct StrategyEvaluator {
+    config: StrategyEvaluatorConfig,
+}
+
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct Execution {
+    pub date: NaiveDate,
+    pub action: Action,
+    pub shares: Shares,
+}
+
+/// part of a buy lot closed by a sell
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct TradeRecord {
+    pub buy_date: NaiveDate,
+    pub buy_price: Price,
+    pub sell_date: NaiveDate,
+    pub sell_price: Price,
+    pub shares: Shares,
+    pub pnl: f64,
+}
+
+/// pairs sells with the oldest open buy lots first, a sell covering several lots yields one
+/// record per lot. covers are paired with the short lots the same way, recorded with the
+/// cover as the buy and the short as the sell
+pub fn round_trips(executions: &[Execution]) -> Vec<TradeRecord> {
+    match_lots(executions).0
+}
+
+/// (buy date, buy price, shares) of the buy lots still open after `executions`, oldest first
+pub fn open_lots(executions: &[Execution]) -> Vec<(NaiveDate, Price, Shares)> {
+    match_lots(executions).1.into()
+}
+
+/// (date, price, shares) of open lots, oldest first
+type Lots = VecDeque<(NaiveDate, Price, Shares)>;
+
+/// (round trips, open buy lots, open short lots)
+fn match_lots(executions: &[Execution]) -> (Vec<TradeRecord>, Lots, Lots) {
+    let mut lots = Lots::new();
+    let mut short_lots = Lots::new();
+    let mut result = Vec::new();
+
+    for execution in executions {
+        match execution.action {
+            Action::Buy(price) => lots.push_back((execution.date, price, execution.shares)),
+            Action::Short(price) => short_lots.push_back((execution.date, price, execution.shares)),
+            Action::Sell(price) => {
+                let mut remaining = execution.shares;
+
+                while remaining > 0.0 {
+                    let Some((buy_date, buy_price, shares)) = lots.front_mut() else {
+                        break;
+                    };
+
+                    let closed = remaining.min(*shares);
+                    result.push(TradeRecord {
+                        buy_date: *buy_date,
+                        buy_price: *buy_price,
+                        sell_date: execution.date,
+                        sell_price: price,
+                        shares: closed,
+                        pnl: (price - *buy_price) * closed,
+                    });
+
+                    remaining -= closed;
+                    *shares -= closed;
+                    if *shares == 0.0 {
+                        lots.pop_front();
+                    }
+                }
+            }
+            Action::Cover(price) => {
+                let mut remaining = execution.shares;
+
+                while remaining > 0.0 {
+                    let Some((short_date, short_price, shares)) = short_lots.front_mut() else {
+                        break;
+                    };
+
+                    let closed = remaining.min(*shares);
+                    result.push(TradeRecord {
+                        buy_date: execution.date,
+                        buy_price: price,
+                        sell_date: *short_date,
+                        sell_price: *short_price,
+                        shares: closed,
+                        pnl: (*short_price - price) * closed,
+                    });
+
+                    remaining -= closed;
+                    *shares -= closed;
+                    if *shares == 0.0 {
+                        short_lots.pop_front();
+                    }
+                }
+            }
+        }
+    }
+
+    (result, lots, short_lots)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StrategyEvaluatorResult {
+    pub stock: Shares,
+    /// shares sold short and not covered yet
+    pub short: Shares,
+    /// shares bought, sold, shorted and covered
+    pub trading: Shares,
+    pub balance: f64,
+    pub invest: f64,
+    pub income: f64,
+    pub dividends: f64,
+    pub fees: f64,
+    /// proceeds net of fees and random slippage over invest, 0.0 when nothing was invested
+    #[serde(with = "lossless_float")]
+    pub roi: f64,
+    /// `roi` without fees and random slippage
+    #[serde(with = "lossless_float")]
+    pub gross_roi: f64,
+    /// return of one share bought at the open of the first buy's day and marked to the last
+    /// close, `(last_close - open) / open`, 0.0 without a buy. unlike the multiple `roi` it is
+    /// 0.0 at breakeven, the excess over buy and hold is `roi - 1.0 - benchmark_roi`
+    #[serde(with = "lossless_float")]
+    pub benchmark_roi: f64,
+    pub max_leverage_used: f64,
+    pub sharpe: f64,
+    pub cagr: f64,
+    #[serde(with = "lossless_float")]
+    pub max_drawdown: f64,
+    #[serde(with = "lossless_float")]
+    pub calmar: f64,
+    pub ulcer_index: f64,
+    pub max_winning_streak: usize,
+    pub max_losing_streak: usize,
+    /// fraction of the round trips with a positive pnl
+    pub win_rate: f64,
+    /// winning pnl over the absolute losing pnl of the round trips, infinite without a loss
+    /// and 0.0 without a win
+    #[serde(with = "lossless_float")]
+    pub profit_factor: f64,
+    pub total_contributions: f64,
+    /// pnl of the closed round trips, before fees. a short earns `(short - cover) * shares`
+    pub realized_pnl: f64,
+    /// open lots marked to the last close, the stock value part of `balance` less its cost
+    /// and the open shorts' proceeds less their value
+    pub unrealized_pnl: f64,
+    /// modified dietz return, contributions weighted by the time they were invested
+    pub money_weighted_return: f64,
+    /// annualized internal rate of return of the capital, contributions and final value
+    pub irr: f64,
+    /// inherited position as a buy at its cost basis on the first evaluated day, the oldest lot
+    /// the sells are matched with
+    pub opening_lot: Option<Execution>,
+    pub executions: Vec<Execution>,
+    /// fifo round trips in the order they were closed, empty unless `with_trade_log`
+    pub trades_log: Vec<TradeRecord>,
+    /// daily mark-to-market equity from the first buy
+    pub equity_curve: EquityCurve,
+}
+
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub enum RankMetric {
+    Roi,
+    Sharpe,
+    Calmar,
+}
+
+impl StrategyEvaluatorResult {
+    /// whether any share was bought or sold, `roi` is 0.0 without
+    pub fn has_trades(&self) -> bool {
+        self.trading != 0.0
+    }
+
+    /// named metric columns for tabulation
+    pub fn to_record(&self) -> Vec<(String, f64)> {
+        [
+            ("stock", self.stock),
+            ("trading", self.trading),
+            ("balance", self.balance),
+            ("invest", self.invest),
+            ("income", self.income),
+            ("dividends", self.dividends),
+            ("fees", self.fees),
+            ("roi", self.roi),
+            ("gross_roi", self.gross_roi),
+            ("benchmark_roi", self.benchmark_roi),
+            ("max_leverage_used", self.max_leverage_used),
+            ("sharpe", self.sharpe),
+            ("cagr", self.cagr),
+            ("max_drawdown", self.max_drawdown),
+            ("calmar", self.calmar),
+            ("ulcer_index", self.ulcer_index),
+            ("max_winning_streak", self.max_winning_streak as f64),
+            ("max_losing_streak", self.max_losing_streak as f64),
+            ("win_rate", self.win_rate),
+            ("profit_factor", self.profit_factor),
+            ("total_contributions", self.total_contributions),
+            ("realized_pnl", self.realized_pnl),
+            ("unrealized_pnl", self.unrealized_pnl),
+            ("money_weighted_return", self.money_weighted_return),
+            ("irr", self.irr),
+            ("short", self.short),
+        ]
+        .into_iter()
+        .map(|(name, value)| (name.to_owned(), value))
+        .collect()
+    }
+
+    /// calendar year -> return of the equity curve in that year
+    pub fn returns_by_year(&self) -> BTreeMap<i32, f64> {
+        metrics::yearly_returns(&self.equity_curve)
+    }
+
+    pub fn metric(&self, metric: RankMetric) -> f64 {
+        match metric {
+            RankMetric::Roi => self.roi,
+            RankMetric::Sharpe => self.sharpe,
+            RankMetric::Calmar => self.calmar,
+        }
+    }
+
+    /// day over day returns of the equity curve net of their beta to the benchmark's returns,
+    /// only dates both have a return on are used
+    pub fn hedged_returns(&self, benchmark: &Stock) -> Vec<f64> {
+        let benchmark_returns = analysis::daily_returns(benchmark);
+        let (returns, benchmark_returns): (Vec<_>, Vec<_>) = self
+            .equity_curve
+            .iter()
+            .tuple_windows()
+            .filter(|((_, prev), _)| **prev > 0.0)
+            .filter_map(|((_, prev), (date, next))| {
+                let benchmark = benchmark_returns.get(date)?;
+                Some((next / prev - 1.0, *benchmark))
+            })
+            .unzip();
+
+        let beta = metrics::beta(&returns, &benchmark_returns);
+        metrics::hedged_returns(&returns, &benchmark_returns, beta)
+    }
+
+    /// sharpe of `hedged_returns`, the risk adjusted return that is not explained by the benchmark
+    pub fn hedged_sharpe(&self, benchmark: &Stock) -> f64 {
+        metrics::sharpe(&self.hedged_returns(benchmark))
+    }
+
+    /// `executions` after the `opening_lot`, the ones the round trips are matched from
+    fn lot_executions(&self) -> Vec<Execution> {
+        self.opening_lot
+            .into_iter()
+            .chain(self.executions.iter().copied())
+            .collect()
+    }
+
+    /// histogram of the round trip returns `sell / buy - 1` in `bucket_width` wide buckets
+    pub fn trade_return_histogram(&self, bucket_width: f64) -> Vec<(f64, usize)> {
+        let returns = round_trips(&self.lot_executions())
+            .into_iter()
+            .map(|trade| trade.sell_price / trade.buy_price - 1.0)
+            .collect_vec();
+
+        metrics::histogram(&returns, bucket_width)
+    }
+
+    /// (year, month) -> number of executed trades, the `opening_lot` included
+    pub fn trades_per_month(&self) -> BTreeMap<(i32, u32), usize> {
+        let mut result = BTreeMap::new();
+
+        for execution in self.lot_executions() {
+            *result
+                .entry((execution.date.year(), execution.date.month()))
+                .or_default() += 1;
+        }
+
+        result
+    }
+}
+
+/// cash, position and bookkeeping carried from one evaluation into the next, see
+/// `StrategyEvaluator::evaluate_from`
+#[derive(Debug, Clone)]
+pub struct EvaluatorState {
+    stock: Shares,
+    // shares sold short, owed back
+    short: Shares,
+    // proceeds of the open short shares at their short prices, before and after random slippage
+    gross_short_proceeds: f64,
+    short_proceeds: f64,
+    trading: Shares,
+    balance: f64,
+    max_leverage_used: f64,
+    invest: f64,
+    income: f64,
+    dividends: f64,
+    fees: f64,
+    // invest and income at the prices before random slippage
+    gross_invest: f64,
+    gross_income: f64,
+    // cost of the held shares at their average buy price
+    cost_basis: f64,
+    executions: Vec<Execution>,
+    // profit or loss marked to each close, shifted onto the capital base when finished
+    pnl_curve: EquityCurve,
+    max_outlay: f64,
+    // (trading day index it settles on, amount)
+    unsettled: VecDeque<(usize, f64)>,
+    slippage: Option<(f64, SplitMix64)>,
+    // scheduled contributions not credited yet, earliest first
+    pending_contributions: VecDeque<(NaiveDate, f64)>,
+    // (date, amount) of every credited contribution
+    contributions: Vec<(NaiveDate, f64)>,
+    last_day: Option<NaiveDate>,
+    // trading days processed so far, indexes the settlement queue
+    days: usize,
+    // open of the first buy's day, the buy of the buy and hold benchmark
+    benchmark_open: Option<Price>,
+}
+
+impl EvaluatorState {
+    fn new(config: &StrategyEvaluatorConfig) -> Self {
+        Self {
+            stock: config.initial_shares as Shares,
+            short: 0.0,
+            gross_short_proceeds: 0.0,
+            short_proceeds: 0.0,
+            trading: 0.0,
+            balance: config.initial_capital,
+            max_leverage_used: 0.0,
+            invest: config.initial_cost_basis * config.initial_shares as f64,
+            income: 0.0,
+            dividends: 0.0,
+            fees: 0.0,
+            gross_invest: config.initial_cost_basis * config.initial_shares as f64,
+            gross_income: 0.0,
+            cost_basis: config.initial_cost_basis * config.initial_shares as f64,
+            executions: Vec::new(),
+            pnl_curve: EquityCurve::new(),
+            max_outlay: 0.0,
+            unsettled: VecDeque::new(),
+            slippage: config
+                .random_slippage
+                .map(|(max_bps, seed)| (max_bps, SplitMix64::new(seed))),
+            pending_contributions: config
+                .contributions
+                .iter()
+                .copied()
+                .sorted_by_key(|(date, _)| *date)
+                .collect(),
+            contributions: Vec::new(),
+            last_day: None,
+            days: 0,
+            benchmark_open: None,
+        }
+    }
+
+    pub fn position(&self) -> Shares {
+        self.stock
+    }
+
+    pub fn short_position(&self) -> Shares {
+        self.short
+    }
+
+    pub fn balance(&self) -> f64 {
+        self.balance
+    }
+}
+
+/// evaluation of one series on top of an `EvaluatorState`
+struct Ledger<'a> {
+    config: &'a StrategyEvaluatorConfig,
+    // bar of the day being processed
+    bar: DaySeriesData,
+    state: EvaluatorState,
+}
+
+impl<'a> Ledger<'a> {
+    fn new(config: &'a StrategyEvaluatorConfig, state: Option<EvaluatorState>) -> Self {
+        Self {
+            config,
+            bar: DaySeriesData::default(),
+            state: state.unwrap_or_else(|| EvaluatorState::new(config)),
+        }
+    }
+
+    /// buys the benchmark at the open of the first day from `start`, unless an earlier segment
+    /// already did
+    fn start_benchmark(&mut self, trades: &BTreeMap<NaiveDate, DaySeriesData>, start: NaiveDate) {
+        if self.state.benchmark_open.is_none() {
+            self.state.benchmark_open = trades.range(start..).next().map(|(_, bar)| bar.open);
+        }
+    }
+
+    fn equity(&self, price: Price) -> f64 {
+        self.state.balance + (self.state.stock - self.state.short) * price
+    }
+
+    /// settles matured cash and credits the dividend and contributions of the next trading
+    /// day
+    fn begin_day(&mut self, today: NaiveDate, bar: &DaySeriesData) {
+        self.bar = *bar;
+        let ix = self.state.days;
+        self.state.days += 1;
+
+        let new_month = self
+            .state
+            .last_day
+            .is_some_and(|last| (last.year(), last.month()) != (today.year(), today.month()));
+        if new_month && self.config.monthly_contribution != 0.0 {
+            self.contribute(today, self.config.monthly_contribution);
+        }
+        while let Some((_, amount)) = self
+            .state
+            .pending_contributions
+            .pop_front_if(|(date, _)| *date <= today)
+        {
+            self.contribute(today, amount);
+        }
+        self.state.last_day = Some(today);
+
+        while self
+            .state
+            .unsettled
+            .front()
+            .is_some_and(|(settle, _)| *settle <= ix)
+        {
+            self.state.unsettled.pop_front();
+        }
+
+        if let Some(dividend) = self.config.dividends.get(&today) {
+            let credit = dividend * self.state.stock;
+            if credit > 0.0 {
+                self.state.dividends += credit;
+                self.state.balance += credit;
+                if self.config.cash_settlement_days > 0 {
+                    self.state
+                        .unsettled
+                        .push_back((ix + self.config.cash_settlement_days, credit));
+                }
+            }
+
+            // the lender of the shorted shares is owed the dividend
+            let owed = dividend * self.state.short;
+            if owed > 0.0 {
+                self.state.dividends -= owed;
+                self.state.balance -= owed;
+            }
+        }
+    }
+
+    fn contribute(&mut self, date: NaiveDate, amount: f64) {
+        self.state.balance += amount;
+        self.state.contributions.push((date, amount));
+    }
+
+    fn total_contributions(&self) -> f64 {
+        self.state
+            .contributions
+            .iter()
+            .map(|(_, amount)| amount)
+            .sum()
+    }
+
+    fn settled_cash(&self) -> f64 {
+        self.state.balance - self.state.unsettled.iter().map(|(_, c)| c).sum::<f64>()
+    }
+
+    /// shares a buy signal asks for according to the configured sizing
+    fn buy_size(&self, date: NaiveDate, price: Price) -> Shares {
+        match self.config.buy_sizing {
+            _ if self.config.in_blackout(date) => 0.0,
+            BuySizing::Shares(shares) => shares as Shares,
+            BuySizing::CashPercent(percent) => {
+                (percent * self.settled_cash().max(0.0) / price).floor()
+            }
+            BuySizing::Cash(cash) => cash / price,
+        }
+    }
+
+    /// scales `shares` down so the long and short positions stay within `max_leverage`
+    fn cap_leverage(&self, price: Price, shares: Shares) -> Shares {
+        if !self.config.max_leverage.is_finite() {
+            return shares;
+        }
+
+        let equity = self.equity(price);
+        let max_stock = if equity > 0.0 {
+            (self.config.max_leverage * equity / price).floor()
+        } else {
+            0.0
+        };
+
+        shares.min((max_stock - self.state.stock - self.state.short).max(0.0))
+    }
+
+    fn check_fill(&self, date: NaiveDate, price: Price) {
+        if self.config.validate_fills {
+            assert!(
+                (self.bar.low..=self.bar.high).contains(&price),
+                "{date} fill at {price} is outside of [{}, {}]",
+                self.bar.low,
+                self.bar.high
+            );
+        }
+    }
+
+    /// price moved against the trade by the random slippage, if any
+    fn slip(&mut self, price: Price, is_buy: bool) -> Price {
+        let Some((max_bps, rng)) = &mut self.state.slippage else {
+            return price;
+        };
+
+        let slippage = rng.next_f64() * *max_bps / 10000.0;
+        if is_buy {
+            price * (1.0 + slippage)
+        } else {
+            price * (1.0 - slippage)
+        }
+    }
+
+    /// (fill price, filled shares) of the execution model, priced by `fill_fn` if configured
+    fn fill(&self, action: Action, shares: Shares) -> (Price, Shares) {
+        let (price, shares) = self.config.execution.fill(&self.bar, action, shares);
+
+        match &self.config.fill_fn {
+            Some(FillFn(fill_fn)) => (fill_fn(&self.bar, &action), shares),
+            None => (price, shares),
+        }
+    }
+
+    fn buy(&mut self, date: NaiveDate, price: Price, shares: Shares) {
+        let (price, shares) = self.fill(Action::Buy(price), shares);
+
+        if shares != 0.0 {
+            self.check_fill(date, price);
+            self.state.gross_invest += price * shares;
+            let price = self.slip(price, true);
+            let fee = self.config.commission.fee(price, shares);
+            self.state.fees += fee;
+            self.state.invest += price * shares;
+            self.state.balance -= price * shares + fee;
+            self.state.stock += shares;
+            self.state.trading += shares;
+            self.state.cost_basis += price * shares;
+            self.state.executions.push(Execution {
+                date,
+                action: Action::Buy(price),
+                shares,
+            });
+
+            if self.config.show_steps {
+                println!("{date} buy  {price}: {shares}, {}", self.state.balance);
+            }
+        }
+
+        self.track_leverage(price);
+    }
+
+    fn sell(&mut self, date: NaiveDate, price: Price, shares: Shares) {
+        let (price, shares) = self.fill(Action::Sell(price), shares.min(self.state.stock));
+
+        if shares != 0.0 {
+            self.check_fill(date, price);
+            self.state.gross_income += price * shares;
+            let price = self.slip(price, false);
+            let fee = self.config.commission.fee(price, shares);
+            self.state.fees += fee;
+            self.state.income += price * shares;
+            self.state.balance += price * shares - fee;
+            self.state.trading += shares;
+            self.state.cost_basis -= self.state.cost_basis * shares / self.state.stock;
+            self.state.stock -= shares;
+            self.state.executions.push(Execution {
+                date,
+                action: Action::Sell(price),
+                shares,
+            });
+
+            if self.config.show_steps {
+                println!("{date} sell {price}: {shares}, {}", self.state.balance);
+            }
+        }
+
+        self.track_leverage(price);
+    }
+
+    /// shorts `shares`, the proceeds count as invested until covered
+    fn short(&mut self, date: NaiveDate, price: Price, shares: Shares) {
+        let (price, shares) = self.fill(Action::Short(price), shares);
+
+        if shares != 0.0 {
+            self.check_fill(date, price);
+            self.state.gross_invest += price * shares;
+            self.state.gross_short_proceeds += price * shares;
+            let price = self.slip(price, false);
+            let fee = self.config.commission.fee(price, shares);
+            self.state.fees += fee;
+            self.state.invest += price * shares;
+            self.state.balance += price * shares - fee;
+            self.state.short += shares;
+            self.state.short_proceeds += price * shares;
+            self.state.trading += shares;
+            self.state.executions.push(Execution {
+                date,
+                action: Action::Short(price),
+                shares,
+            });
+
+            if self.config.show_steps {
+                println!("{date} short {price}: {shares}, {}", self.state.balance);
+            }
+        }
+
+        self.track_leverage(price);
+    }
+
+    /// covers `shares` of the short position at their average short price, the proceeds
+    /// plus `(short - cover) * shares` are the income
+    fn cover(&mut self, date: NaiveDate, price: Price, shares: Shares) {
+        let (price, shares) = self.fill(Action::Cover(price), shares.min(self.state.short));
+
+        if shares != 0.0 {
+            self.check_fill(date, price);
+            let part = shares / self.state.short;
+            let gross_proceeds = self.state.gross_short_proceeds * part;
+            self.state.gross_income += 2.0 * gross_proceeds - price * shares;
+            self.state.gross_short_proceeds -= gross_proceeds;
+            let price = self.slip(price, true);
+            let proceeds = self.state.short_proceeds * part;
+            let fee = self.config.commission.fee(price, shares);
+            self.state.fees += fee;
+            self.state.income += 2.0 * proceeds - price * shares;
+            self.state.balance -= price * shares + fee;
+            self.state.short -= shares;
+            self.state.short_proceeds -= proceeds;
+            self.state.trading += shares;
+            self.state.executions.push(Execution {
+                date,
+                action: Action::Cover(price),
+                shares,
+            });
+
+            if self.config.show_steps {
+                println!("{date} cover {price}: {shares}, {}", self.state.balance);
+            }
+        }
+
+        self.track_leverage(price);
+    }
+
+    /// sells the whole position at `close` once it is more than the stoploss below the
+    /// average cost
+    fn check_stoploss(&mut self, date: NaiveDate, close: Price) {
+        let Some(stoploss) = self.config.stoploss else {
+            return;
+        };
+
+        if self.state.stock > 0.0
+            && close / (self.state.cost_basis / self.state.stock) - 1.0 < -stoploss
+        {
+            self.sell(date, close, self.state.stock);
+        }
+    }
+
+    fn track_leverage(&mut self, price: Price) {
+        let equity = self.equity(price);
+        if equity > 0.0 {
+            self.state.max_leverage_used = self
+                .state
+                .max_leverage_used
+                .max((self.state.stock + self.state.short) * price / equity);
+        }
+    }
+
+    fn mark(&mut self, today: NaiveDate, close: Price) {
+        let deposited = self.config.initial_capital + self.total_contributions();
+        self.state.max_outlay = self.state.max_outlay.max(deposited - self.state.balance);
+        self.state
+            .pnl_curve
+            .insert(today, self.equity(close) - deposited);
+    }
+
+    /// modified dietz return of `pnl` on the capital base, each contribution weighted by the
+    /// share of the period it was invested
+    fn money_weighted_return(&self, capital: f64, pnl: f64) -> f64 {
+        let (Some((start, _)), Some((end, _))) = (
+            self.state.pnl_curve.first_key_value(),
+            self.state.pnl_curve.last_key_value(),
+        ) else {
+            return 0.0;
+        };
+
+        let period = (*end - *start).num_days().max(1) as f64;
+        let weighted = self
+            .state
+            .contributions
+            .iter()
+            .map(|(date, amount)| amount * (*end - *date).num_days() as f64 / period)
+            .sum::<f64>();
+
+        let base = capital + weighted;
+        if base > 0.0 {
+            pnl / base
+        } else {
+            0.0
+        }
+    }
+
+    /// irr of depositing `capital` on the first day and the contributions when credited,
+    /// then withdrawing everything on the last day, 0.0 when there is no solution
+    fn irr(&self, capital: f64, pnl: f64) -> f64 {
+        let (Some((start, _)), Some((end, _))) = (
+            self.state.pnl_curve.first_key_value(),
+            self.state.pnl_curve.last_key_value(),
+        ) else {
+            return 0.0;
+        };
+
+        let deposited = capital + self.total_contributions();
+        let cashflows = std::iter::once((*start, -capital))
+            .chain(
+                self.state
+                    .contributions
+                    .iter()
+                    .map(|(date, amount)| (*date, -amount)),
+            )
+            .chain(std::iter::once((*end, deposited + pnl)))
+            .collect_vec();
+
+        metrics::irr(&cashflows).unwrap_or(0.0)
+    }
+
+    fn trade_cap_reached(&self) -> bool {
+        self.config
+            .max_trades
+            .is_some_and(|max_trades| self.state.executions.len() >= max_trades)
+    }
+
+    /// liquidates on `date` if configured and finishes with the position valued at `close`
+    fn close_out(mut self, date: NaiveDate, close: Price) -> StrategyEvaluatorResult {
+        if self.config.liquidate_at_end && (self.state.stock != 0.0 || self.state.short != 0.0) {
+            self.sell(date, close, self.state.stock);
+            self.cover(date, close, self.state.short);
+            self.mark(date, close);
+        }
+
+        self.finish(close)
+    }
+
+    fn finish(self, last_close: Price) -> StrategyEvaluatorResult {
+        // without starting capital the most cash ever tied up acts as the account size
+        let capital = if self.config.initial_capital > 0.0 {
+            self.config.initial_capital
+        } else {
+            self.state.max_outlay
+        };
+
+        let stock_value = self.state.stock * last_close;
+        // the open shorts' proceeds plus their pnl, as if covered at the last close
+        let short_value = self.state.short * last_close;
+        let short_proceeds = 2.0 * self.state.short_proceeds - short_value;
+        let gross_short_proceeds = 2.0 * self.state.gross_short_proceeds - short_value;
+        // nothing invested, e.g. without any buy, has no return instead of a NaN one
+        let ratio = |proceeds: f64, invest: f64| {
+            if invest == 0.0 {
+                0.0
+            } else {
+                proceeds / invest
+            }
+        };
+        let roi = ratio(
+            self.state.income + self.state.dividends - self.state.fees
+                + stock_value
+                + short_proceeds,
+            self.state.invest,
+        );
+        let total_contributions = self.total_contributions();
+        let pnl = self.state.balance + stock_value
+            - short_value
+            - self.config.initial_capital
+            - total_contributions;
+        let money_weighted_return = self.money_weighted_return(capital, pnl);
+        let irr = self.irr(capital, pnl);
+
+        let equity_curve: EquityCurve = self
+            .state
+            .pnl_curve
+            .into_iter()
+            .map(|(date, pnl)| (date, capital + pnl))
+            .collect();
+
+        let (returns, cagr, max_drawdown) = if self.config.log_metrics {
+            (
+                metrics::log_returns(&equity_curve),
+                metrics::log_cagr(&equity_curve),
+                metrics::log_max_drawdown(&equity_curve),
+            )
+        } else {
+            (
+                metrics::daily_returns(&equity_curve),
+                metrics::cagr(&equity_curve),
+                metrics::max_drawdown(&equity_curve),
+            )
+        };
+        let excess_returns = returns
+            .iter()
+            .map(|ret| ret - self.config.risk_free_rate)
+            .collect::<Vec<_>>();
+        let sharpe = metrics::sharpe(&excess_returns);
+
+        // the inherited position is the oldest lot
+        let opening_lot = (self.config.initial_shares != 0).then(|| Execution {
+            date: equity_curve
+                .keys()
+                .next()
+                .copied()
+                .unwrap_or(NaiveDate::MIN),
+            action: Action::Buy(self.config.initial_cost_basis),
+            shares: self.config.initial_shares as Shares,
+        });
+        let executions = opening_lot
+            .into_iter()
+            .chain(self.state.executions.iter().copied())
+            .collect_vec();
+        let (closed, open, open_shorts) = match_lots(&executions);
+
+        let pnls = closed.iter().map(|trade| trade.pnl).collect_vec();
+        let realized_pnl = pnls.iter().sum::<f64>();
+        let unrealized_pnl = open
+            .iter()
+            .map(|(_, price, shares)| (last_close - price) * shares)
+            .chain(
+                open_shorts
+                    .iter()
+                    .map(|(_, price, shares)| (price - last_close) * shares),
+            )
+            .sum::<f64>();
+        let (max_winning_streak, max_losing_streak) = metrics::max_streaks(&pnls);
+        let trades_log = if self.config.trade_log {
+            closed
+        } else {
+            Vec::new()
+        };
+
+        StrategyEvaluatorResult {
+            stock: self.state.stock,
+            short: self.state.short,
+            trading: self.state.trading,
+            balance: self.state.balance + stock_value - short_value,
+            invest: self.state.invest,
+            income: self.state.income,
+            dividends: self.state.dividends,
+            fees: self.state.fees,
+            roi,
+            gross_roi: ratio(
+                self.state.gross_income + self.state.dividends + stock_value + gross_short_proceeds,
+                self.state.gross_invest,
+            ),
+            benchmark_roi: self
+                .state
+                .benchmark_open
+                .map_or(0.0, |open| (last_close - open) / open),
+            max_leverage_used: self.state.max_leverage_used,
+            sharpe,
+            cagr,
+            max_drawdown,
+            calmar: metrics::calmar(cagr, max_drawdown),
+            ulcer_index: metrics::ulcer_index(&equity_curve),
+            max_winning_streak,
+            max_losing_streak,
+            win_rate: metrics::win_rate(&pnls),
+            profit_factor: metrics::profit_factor(&pnls),
+            total_contributions,
+            realized_pnl,
+            unrealized_pnl,
+            money_weighted_return,
+            irr,
+            opening_lot,
+            executions: self.state.executions,
+            trades_log,
+            equity_curve,
+        }
+    }
+}
+
+impl StrategyEvaluator {
+    pub fn evaluate<T>(
+        &self,
+        strategy: T,
+        folders: Vec<Box<dyn FoldStrategy>>,
+        trades: &BTreeMap<NaiveDate, DaySeriesData>,
+    ) -> error::Result<StrategyEvaluatorResult>
+    where
+        T: BuySellStrategy,
+    {
+        self.evaluate_with(&strategy, &folders, trades)
+    }
+
+    /// `evaluate` borrowing the strategy and folders so they can be reused across stocks
+    pub fn evaluate_with<T>(
+        &self,
+        strategy: &T,
+        folders: &[Box<dyn FoldStrategy>],
+        trades: &BTreeMap<NaiveDate, DaySeriesData>,
+    ) -> error::Result<StrategyEvaluatorResult>
+    where
+        T: BuySellStrategy + ?Sized,
+    {
+        let (ledger, (date, close)) = self.run(strategy, folders, trades, None, None)?;
+        Ok(ledger.close_out(date, close))
+    }
+
+    /// `evaluate_with` of the bars of `trades` from `start` on, continuing from the state a
+    /// previous segment ended in, if any, and returning the state this one ends in. the signals
+    /// are computed over all of `trades` so the bars before `start` warm the strategy up, and a
+    /// continued segment is evaluated from its first bar and ignores actions dated before it.
+    /// chaining consecutive segments, each given the history up to its end, gives the result of
+    /// evaluating them at once
+    pub fn evaluate_from<T>(
+        &self,
+        strategy: &T,
+        folders: &[Box<dyn FoldStrategy>],
+        trades: &BTreeMap<NaiveDate, DaySeriesData>,
+        start: NaiveDate,
+        state: Option<EvaluatorState>,
+    ) -> error::Result<(StrategyEvaluatorResult, EvaluatorState)>
+    where
+        T: BuySellStrategy + ?Sized,
+    {
+        let (ledger, (date, close)) = self.run(strategy, folders, trades, Some(start), state)?;
+        let state = ledger.state.clone();
+        Ok((ledger.close_out(date, close), state))
+    }
+
+    /// ledger after the last evaluated day, with that day's date and close. the signals are
+    /// computed over all of `trades`, the bars before `start` are not evaluated
+    fn run<T>(
+        &self,
+        strategy: &T,
+        folders: &[Box<dyn FoldStrategy>],
+        trades: &BTreeMap<NaiveDate, DaySeriesData>,
+        start: Option<NaiveDate>,
+        state: Option<EvaluatorState>,
+    ) -> error::Result<(Ledger<'_>, (NaiveDate, Price))>
+    where
+        T: BuySellStrategy + ?Sized,
+    {
+        let Some(first_day) = trades
+            .range(start.unwrap_or(NaiveDate::MIN)..)
+            .next()
+            .map(|(date, _)| *date)
+        else {
+            return Err(StockFilterError::EmptyTrades);
+        };
+
+        let actions = folders
+            .iter()
+            .fold(strategy.buy_sell(trades), |actions, folder| {
+                folder.fold(actions, trades)
+            });
+        let actions = resolve_same_day(actions, self.config.same_day_policy)
+            .into_iter()
+            .skip(self.config.skip_first_n_signals)
+            .collect_vec();
+
+        let continued = state.is_some();
+        let mut ledger = Ledger::new(&self.config, state);
+
+        // an inherited position can be sold before anything is bought
+        let first_buy = actions
+            .iter()
+            .find_position(|(date, act)| {
+                *date >= first_day
+                    && (act.is_buy() || act.is_short() || self.config.initial_shares != 0)
+            })
+            .map(|(first_buy, (first_buy_date, _))| (first_buy, *first_buy_date));
+
+        let (first_action, start_date) = if continued {
+            let first_action = actions
+                .iter()
+                .position(|(date, _)| *date >= first_day)
+                .unwrap_or(actions.len());
+            (first_action, first_day)
+        } else {
+            // without a buy, a short or an inherited position every bar is evaluated without
+            // trading
+            first_buy.unwrap_or((actions.len(), first_day))
+        };
+
+        if let Some((_, first_buy_date)) = first_buy {
+            ledger.start_benchmark(trades, first_buy_date);
+        }
+
+        let mut actions = actions.into_iter().skip(first_action).peekable();
+
+        let mut last = trades.last_key_value().unwrap();
+
+        for (today, data) in trades.range(start_date..) {
+            ledger.begin_day(*today, data);
+            last = (today, data);
+
+            while let Some((date, act)) = actions.next_if(|(date, _)| date <= today) {
+                if ledger.trade_cap_reached() {
+                    break;
+                }
+
+                match act {
+                    Action::Buy(price) => {
+                        let shares = ledger.cap_leverage(price, ledger.buy_size(date, price));
+                        ledger.buy(date, price, shares);
+                    }
+                    Action::Sell(price) => {
+                        // whole positions keep selling whole shares
+                        let shares = ledger.state.stock * self.config.sell_factor;
+                        let shares = if ledger.state.stock.fract() == 0.0 {
+                            shares.floor()
+                        } else {
+                            shares
+                        };
+                        ledger.sell(date, price, shares);
+                    }
+                    Action::Short(price) => {
+                        let shares = ledger.cap_leverage(price, ledger.buy_size(date, price));
+                        ledger.short(date, price, shares);
+                    }
+                    // covers the whole short position
+                    Action::Cover(price) => ledger.cover(date, price, ledger.state.short),
+                }
+            }
+
+            if !ledger.trade_cap_reached() {
+                ledger.check_stoploss(*today, data.close);
+            }
+
+            ledger.mark(*today, data.close);
+
+            if ledger.trade_cap_reached() {
+                break;
+            }
+        }
+
+        Ok((ledger, (*last.0, last.1.close)))
+    }
+
+    /// trades the difference to each bar's target weight at its close, starting from the
+    /// first positive weight
+    pub fn evaluate_weights<T>(
+        &self,
+        strategy: T,
+        trades: &BTreeMap<NaiveDate, DaySeriesData>,
+    ) -> error::Result<StrategyEvaluatorResult>
+    where
+        T: TargetWeightStrategy,
+    {
+        if trades.is_empty() {
+            return Err(StockFilterError::EmptyTrades);
+        }
+
+        let weights: BTreeMap<NaiveDate, f64> = strategy.weights(trades).into_iter().collect();
+        let first_buy = weights
+            .iter()
+            .find(|(_, weight)| **weight > 0.0)
+            .map(|(date, _)| *date);
+        let start = first_buy.unwrap_or(*trades.first_key_value().unwrap().0);
+
+        let mut ledger = Ledger::new(&self.config, None);
+        if let Some(first_buy) = first_buy {
+            ledger.start_benchmark(trades, first_buy);
+        }
+        let mut target = 0f64;
+
+        let mut last = trades.last_key_value().unwrap();
+
+        for (today, data) in trades.range(start..) {
+            ledger.begin_day(*today, data);
+            last = (today, data);
+
+            if let Some(weight) = weights.get(today) {
+                target = *weight;
+            }
+
+            let price = data.close;
+            let target_stock = (target * ledger.equity(price).max(0.0) / price).floor();
+
+            if target_stock > ledger.state.stock {
+                let shares = ledger.cap_leverage(price, target_stock - ledger.state.stock);
+                ledger.buy(*today, price, shares);
+            } else if target_stock < ledger.state.stock {
+                ledger.sell(*today, price, ledger.state.stock - target_stock);
+            }
+
+            ledger.mark(*today, data.close);
+
+            if ledger.trade_cap_reached() {
+                break;
+            }
+        }
+
+        Ok(ledger.close_out(*last.0, last.1.close))
+    }
+}
+
+/// evaluates every strategy on the same trades, best `rank_metric` of the config first
+pub fn compare_strategies(
+    strategies: Vec<(String, Box<dyn BuySellStrategy>)>,
+    folders: &[Box<dyn FoldStrategy>],
+    trades: &BTreeMap<NaiveDate, DaySeriesData>,
+    config: StrategyEvaluatorConfig,
+) -> error::Result<Vec<(String, StrategyEvaluatorResult)>> {
+    let metric = config.rank_metric;
+    let evaluator = StrategyEvaluator { config };
+
+    let mut results = strategies
+        .into_iter()
+        .map(|(name, strategy)| {
+            Ok((
+                name,
+                evaluator.evaluate_with(strategy.as_ref(), folders, trades)?,
+            ))
+        })
+        .collect::<error::Result<Vec<_>>>()?;
+    results.sort_by(|(_, l), (_, r)| r.metric(metric).total_cmp(&l.metric(metric)));
+
+    Ok(results)
+}
+
+/// evaluates every stock with the fold pipeline of its market, stocks of markets without a
+/// pipeline are evaluated without folds
+pub fn backtest_market(
+    stocks: &[Stock],
+    strategy: &dyn BuySellStrategy,
+    pipelines: &HashMap<StockMarket, Vec<Box<dyn FoldStrategy>>>,
+    config: StrategyEvaluatorConfig,
+) -> error::Result<Vec<(String, StrategyEvaluatorResult)>> {
+    let evaluator = StrategyEvaluator { config };
+
+    stocks
+        .iter()
+        .filter(|stock| !stock.trades.is_empty())
+        .map(|stock| {
+            let folders = pipelines
+                .get(&stock.market)
+                .map(Vec::as_slice)
+                .unwrap_or_default();
+            Ok((
+                stock.code.clone(),
+                evaluator.evaluate_with(strategy, folders, &stock.trades)?,
+            ))
+        })
+        .collect()
+}
+
+/// named strategy parameters of a sweep row
+pub type Params = Vec<(String, f64)>;
+
+/// one csv row per result, parameter columns first, column names from the first row
+pub fn write_records_csv(
+    writer: &mut impl Write,
+    rows: &[(Params, StrategyEvaluatorResult)],
+) -> error::Result<()> {
+    let Some((params, result)) = rows.first() else {
+        return Ok(());
+    };
+
+    let header = params
+        .iter()
+        .chain(&result.to_record())
+        .map(|(name, _)| name.clone())
+        .join(",");
+    writeln!(writer, "{header}")?;
+
+    for (params, result) in rows {
+        let row = params
+            .iter()
+            .chain(&result.to_record())
+            .map(|(_, value)| value.to_string())
+            .join(",");
+        writeln!(writer, "{row}")?;
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+/// `date,action,price` csv of `actions`, the action as `BUY`, `SELL`, `SHORT` or `COVER`
+pub fn write_actions_csv(
+    writer: &mut impl Write,
+    actions: &[(NaiveDate, Action)],
+) -> error::Result<()> {
+    writeln!(writer, "date,action,price")?;
+
+    for (date, act) in actions {
+        let name = match act {
+            Action::Buy(_) => "BUY",
+            Action::Sell(_) => "SELL",
+            Action::Short(_) => "SHORT",
+            Action::Cover(_) => "COVER",
+        };
+        writeln!(writer, "{date},{name},{}", act.price())?;
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+/// json object of the results keyed by their stock codes, a later duplicate code wins
+pub fn write_results_json(
+    writer: &mut impl Write,
+    results: &[(String, StrategyEvaluatorResult)],
+) -> error::Result<()> {
+    let results: BTreeMap<&str, &StrategyEvaluatorResult> = results
+        .iter()
+        .map(|(code, result)| (code.as_str(), result))
+        .collect();
+
+    serde_json::to_writer(&mut *writer, &results)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// one backtest as a single archivable json document
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BacktestReport {
+    pub config: StrategyEvaluatorConfig,
+    pub params: Params,
+    pub metrics: Vec<(String, Option<f64>)>,
+    pub trades: Vec<TradeRecord>,
+    pub result: StrategyEvaluatorResult,
+}
+
+impl BacktestReport {
+    /// bundles `result` with the config and parameters it was evaluated with, non-finite
+    /// metrics become `None`
+    pub fn new(
+        config: &StrategyEvaluatorConfig,
+        params: Params,
+        result: StrategyEvaluatorResult,
+    ) -> Self {
+        Self {
+            config: config.clone(),
+            params,
+            metrics: result
+                .to_record()
+                .into_iter()
+                .map(|(name, value)| (name, value.is_finite().then_some(value)))
+                .collect(),
+            trades: round_trips(&result.executions),
+            result,
+        }
+    }
+
+    pub fn to_json(&self) -> error::Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn from_json(json: &str) -> error::Result<Self> {
+        Ok(serde_json::from_str(json)?)
+    }
+}
+
+#[derive(Debug, Copy, Clone, Default)]
+pub struct PortfolioSummary {
+    pub total_invested: f64,
+    pub total_income: f64,
+    /// final value of every position over everything invested, 0.0 when nothing was invested
+    pub roi: f64,
+    pub mean_roi: f64,
+    pub median_roi: f64,
+    /// fraction of results with `roi > 1.0`
+    pub profitable: f64,
+}
+
+pub fn aggregate_results(results: &[(String, StrategyEvaluatorResult)]) -> PortfolioSummary {
+    if results.is_empty() {
+        return PortfolioSummary::default();
+    }
+
+    let total_invested = results.iter().map(|(_, r)| r.invest).sum::<f64>();
+    let total_value = results.iter().map(|(_, r)| r.roi * r.invest).sum::<f64>();
+    let rois = results
+        .iter()
+        .map(|(_, r)| r.roi)
+        .sorted_by(f64::total_cmp)
+        .collect_vec();
+
+    let median_roi = if rois.len() % 2 == 0 {
+        (rois[rois.len() / 2 - 1] + rois[rois.len() / 2]) / 2.0
+    } else {
+        rois[rois.len() / 2]
+    };
+
+    PortfolioSummary {
+        total_invested,
+        total_income: results.iter().map(|(_, r)| r.income).sum(),
+        roi: if total_invested == 0.0 {
+            0.0
+        } else {
+            total_value / total_invested
+        },
+        mean_roi: metrics::mean(&rois),
+        median_roi,
+        profitable: rois.iter().filter(|roi| **roi > 1.0).count() as f64 / rois.len() as f64,
+    }
+}
+
+//
+
+#[derive(Debug, Clone, Default)]
+pub struct Account {
+    pub balance: Price,
+    /// code -> shares held
+    pub stocks: HashMap<String, usize>,
+}
+
+pub struct StockInfo<'a> {
+    pub code: String,
+    /// every bar of the stock, the ones after `date` are not known yet
+    pub past_trades: &'a BTreeMap<NaiveDate, DaySeriesData>,
+    pub date: NaiveDate,
+}
+
+impl StockInfo<'_> {
+    pub fn today(&self) -> &DaySeriesData {
+        &self.past_trades[&self.date]
+    }
+}
+
+pub trait LinearBuySellStrategy {
+    fn buy(&self, account: &Account, stock: &StockInfo) -> bool;
+
+    /// sells the whole holding of the stock
+    fn sell(&self, _account: &Account, _stock: &StockInfo) -> bool {
+        false
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct BackTestResult {
+    pub account: Account,
+    /// pnl of the sold shares against their average cost
+    pub realized_pnl: f64,
+    /// buy signals the balance could not afford a share for
+    pub skipped_buys: usize,
+}
+
+/// walks the bars of every stock in date order and trades one share per buy signal at the
+/// close, as long as the balance affords it
+pub struct BackTester {}
+
+impl BackTester {
+    pub fn run<T>(&self, strategy: &T, stocks: &[Stock], balance: Price) -> BackTestResult
+    where
+        T: LinearBuySellStrategy + ?Sized,
+    {
+        let mut result = BackTestResult {
+            account: Account {
+                balance,
+                stocks: HashMap::new(),
+            },
+            ..Default::default()
+        };
+        // code -> invested in the held shares
+        let mut costs: HashMap<String, f64> = HashMap::new();
+
+        let dates: BTreeSet<NaiveDate> = stocks
+            .iter()
+            .flat_map(|stock| stock.trades.keys().copied())
+            .collect();
+
+        for date in dates {
+            for stock in stocks.iter().filter(|s| s.trades.contains_key(&date)) {
+                let info = StockInfo {
+                    code: stock.code.clone(),
+                    past_trades: &stock.trades,
+                    date,
+                };
+                let close = info.today().close;
+                let account = &mut result.account;
+                let held = account.stocks.get(&stock.code).copied().unwrap_or(0);
+
+                if held != 0 && strategy.sell(account, &info) {
+                    let cost = costs.remove(&stock.code).unwrap_or(0.0);
+                    result.realized_pnl += close * held as f64 - cost;
+                    account.balance += close * held as f64;
+                    account.stocks.remove(&stock.code);
+                } else if strategy.buy(account, &info) {
+                    if account.balance < close {
+                        result.skipped_buys += 1;
+                        continue;
+                    }
+
+                    account.balance -= close;
+                    *account.stocks.entry(stock.code.clone()).or_default() += 1;
+                    *costs.entry(stock.code.clone()).or_default() += close;
+                }
+            }
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use chrono::NaiveDate;
+
+    use crate::{
+        error,
+        error::StockFilterError,
+        fixtures::{
+            date, day, stock_from_closes, trades_from_closes, ymd, FixedStrategy, FixedWeights,
+        },
+        loader::{KospiLoader, StockDataLoader},
+        metrics,
+        model::{DaySeriesData, Price, Stock, StockMarket},
+        strategy::{
+            aggregate_results, backtest_market, compare_strategies, open_lots, resolve_same_day,
+            round_trips, write_actions_csv, write_records_csv, write_results_json, Account, Action,
+            AtrStopStrategy, BackTester, BacktestReport, BeginningSurpriseStrategy,
+            BollingerStrategy, BuySellStrategy, BuySizing, CombineMode, CommissionModel,
+            CompositeStrategy, ConsecutiveBuyRemover, ConsecutiveSellRemover, CooldownFold,
+            DailyLossLimitFold, ExecutionModel, FoldPipeline, FoldStage, FoldStrategy, InvertFold,
+            KaufmanAmaStrategy, LinearBuySellStrategy, LossSellRemover, MacdStrategy,
+            MovingAverageCrossStrategy, NaiveStrategy, NeverSellStrategy,
+            OpeningRangeBreakoutStrategy, RankMetric, RegressionChannelStrategy, RsiStrategy,
+            SameDayPolicy, SignalWeights, StockInfo, StrategyEvaluator, StrategyEvaluatorConfig,
+            StrategyEvaluatorResult, TakeProfitFold, TargetWeightStrategy, TrailingStopFold,
+            TrendPullbackStrategy, VolumeSpikeStrategy, WeightedBlendStrategy, ZScoreStrategy,
+        },
+    };
+
+    #[test]
+    fn unittest_naive_strategy() -> eyre::Result<()> {
+        let stocks = KospiLoader::load()?;
+        let stock = stocks.into_iter().find(|s| s.name == "SK하이닉스").unwrap();
+
+        println!("code: {}", stock.code);
+
+        let strategy = NaiveStrategy {
+            buy_move: 20,
+            sell_move: 20,
+            breakout_margin: 0.0,
+        };
+
+        let folder = vec![
+            ConsecutiveBuyRemover {}.boxed(),
+            LossSellRemover {}.boxed(),
+            // NeverSellStrategy {}.boxed(),
+        ];
+
+        let r = StrategyEvaluator {
+            config: StrategyEvaluatorConfig::default().with_show_steps(true),
+        }
+        .evaluate(strategy, folder, &stock.trades)
+        .unwrap();
+
+        println!("result: {r:#?}");
+
+        Ok(())
+    }
+
+    #[test]
+    fn unittest_trades_per_month() {
+        let trades = [
+            (ymd(2024, 1, 2), 10.0),
+            (ymd(2024, 1, 15), 11.0),
+            (ymd(2024, 1, 20), 12.0),
+            (ymd(2024, 2, 5), 13.0),
+            (ymd(2024, 4, 1), 12.0),
+            (ymd(2024, 4, 2), 14.0),
+        ]
+        .into_iter()
+        .map(|(date, close)| (date, day(close)))
+        .collect();
+
+        let strategy = FixedStrategy(vec![
+            (ymd(2024, 1, 2), Action::Buy(10.0)),
+            (ymd(2024, 1, 15), Action::Sell(11.0)),
+            (ymd(2024, 1, 20), Action::Buy(12.0)),
+            (ymd(2024, 2, 5), Action::Sell(13.0)),
+            (ymd(2024, 4, 1), Action::Buy(12.0)),
+            (ymd(2024, 4, 2), Action::Sell(14.0)),
+        ]);
+
+        let r = StrategyEvaluator {
+            config: StrategyEvaluatorConfig::default(),
+        }
+        .evaluate(strategy, vec![], &trades)
+        .unwrap();
+
+        assert_eq!(
+            r.trades_per_month().into_iter().collect::<Vec<_>>(),
+            vec![((2024, 1), 3), ((2024, 2), 1), ((2024, 4), 2)]
+        );
+
+        // the inherited share is bought on the first evaluated day
+        let r = StrategyEvaluator {
+            config: StrategyEvaluatorConfig::default().with_initial_position(1, 9.0),
+        }
+        .evaluate(
+            FixedStrategy(vec![
+                (ymd(2024, 1, 15), Action::Sell(11.0)),
+                (ymd(2024, 2, 5), Action::Buy(13.0)),
+            ]),
+            vec![],
+            &trades,
+        )
+        .unwrap();
+
+        assert_eq!(
+            r.trades_per_month().into_iter().collect::<Vec<_>>(),
+            vec![((2024, 1), 2), ((2024, 2), 1)]
+        );
+    }
+
+    #[test]
+    fn unittest_max_leverage() {
+        let trades = trades_from_closes(&[100.0, 100.0, 100.0]);
+        let strategy = FixedStrategy(vec![
+            (date(0), Action::Buy(100.0)),
+            (date(1), Action::Buy(100.0)),
+        ]);
+
+        let r = StrategyEvaluator {
+            config: StrategyEvaluatorConfig::default()
+                .with_buy_sizing(BuySizing::Shares(6))
+                .with_initial_capital(1000.0)
+                .with_max_leverage(1.0),
+        }
+        .evaluate(strategy, vec![], &trades)
+        .unwrap();
+
+        // the second buy of 6 would need 1200 in a 1000 account, only 4 fit
+        assert_eq!(r.stock, 10.0);
+        assert_eq!(r.executions[1].shares, 4.0);
+        assert!((r.max_leverage_used - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn unittest_cash_settlement_days() {
+        let trades = trades_from_closes(&[100.0; 5]);
+        let strategy = || {
+            FixedStrategy(vec![
+                (date(0), Action::Buy(100.0)),
+                (date(2), Action::Buy(100.0)),
+                (date(3), Action::Buy(100.0)),
+            ])
+        };
+        let config = || {
+            StrategyEvaluatorConfig::default()
+                .with_buy_sizing(BuySizing::CashPercent(1.0))
+                .with_initial_capital(100.0)
+                .with_dividends([(date(1), 100.0)].into_iter().collect())
+        };
+
+        let r = StrategyEvaluator { config: config() }
+            .evaluate(strategy(), vec![], &trades)
+            .unwrap();
+        assert_eq!(
+            r.executions.iter().map(|e| e.date).collect::<Vec<_>>(),
+            vec![date(0), date(2)]
+        );
+
+        // the dividend credited on day 1 is only spendable from day 3
+        let r = StrategyEvaluator {
+            config: config().with_cash_settlement_days(2),
+        }
+        .evaluate(strategy(), vec![], &trades)
+        .unwrap();
+        assert_eq!(
+            r.executions.iter().map(|e| e.date).collect::<Vec<_>>(),
+            vec![date(0), date(3)]
+        );
+        assert_eq!(r.dividends, 100.0);
+    }
+
+    #[test]
+    fn unittest_regression_channel_strategy() {
+        let mut closes = (0..50)
+            .map(|ix| 100.0 + ix as f64 + if ix % 2 == 0 { 0.5 } else { -0.5 })
+            .collect::<Vec<_>>();
+        closes[30] -= 5.0;
+        closes[40] += 5.0;
+        let trades = trades_from_closes(&closes);
+
+        let strategy = RegressionChannelStrategy {
+            period: 10,
+            num_std: 2.0,
+        };
+
+        let buys = strategy.buy(&trades);
+        let sells = strategy.sell(&trades);
+
+        assert_eq!(buys.first().unwrap().0, date(30));
+        assert_eq!(sells.first().unwrap().0, date(40));
+    }
+
+    #[test]
+    fn unittest_commission_model() {
+        let trades = trades_from_closes(&[50.0, 60.0]);
+        let evaluate = |commission| {
+            StrategyEvaluator {
+                config: StrategyEvaluatorConfig::default()
+                    .with_buy_sizing(BuySizing::Shares(100))
+                    .with_commission(commission),
+            }
+            .evaluate(
+                FixedStrategy(vec![
+                    (date(0), Action::Buy(50.0)),
+                    (date(1), Action::Sell(60.0)),
+                ]),
+                vec![],
+                &trades,
+            )
+            .unwrap()
+        };
+
+        let per_share = evaluate(CommissionModel::per_share(0.01));
+        let percentage = evaluate(CommissionModel::percentage(0.001));
+        let minimum = evaluate(CommissionModel::per_share(0.01).with_min(1.5));
+        // the minimum floors the whole fee, it binds on the buy of 1.0 + 5.0 but not on the
+        // sell of 1.0 + 6.0
+        let minimum_and_rate = evaluate(CommissionModel {
+            rate: 0.001,
+            ..CommissionModel::per_share(0.01).with_min(6.5)
+        });
+
+        assert!((per_share.fees - 2.0).abs() < 1e-9);
+        assert!((percentage.fees - 11.0).abs() < 1e-9);
+        assert!((minimum.fees - 3.0).abs() < 1e-9);
+        assert!((minimum_and_rate.fees - (6.5 + 7.0)).abs() < 1e-9);
+        assert!(percentage.roi < per_share.roi);
+        assert!((per_share.balance - (1000.0 - 2.0)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn unittest_calmar() {
+        let trades = [
+            (ymd(2024, 1, 1), 100.0),
+            (ymd(2024, 7, 1), 50.0),
+            (ymd(2025, 1, 1), 100.0),
+            (ymd(2026, 1, 1), 121.0),
+        ]
+        .into_iter()
+        .map(|(date, close)| (date, day(close)))
+        .collect();
+
+        let r = StrategyEvaluator {
+            config: StrategyEvaluatorConfig::default(),
+        }
+        .evaluate(
+            FixedStrategy(vec![(ymd(2024, 1, 1), Action::Buy(100.0))]),
+            vec![],
+            &trades,
+        )
+        .unwrap();
+
+        assert_eq!(r.max_drawdown, 0.5);
+        assert!((r.cagr - 0.1).abs() < 1e-3);
+        assert_eq!(r.calmar, r.cagr / r.max_drawdown);
+    }
+
+    #[test]
+    fn unittest_benchmark_roi() {
+        let mut trades = trades_from_closes(&[10.0, 12.0, 9.0, 11.0, 14.0, 13.0]);
+        trades.get_mut(&date(2)).unwrap().open = 10.0;
+
+        let r = StrategyEvaluator {
+            config: StrategyEvaluatorConfig::default(),
+        }
+        .evaluate(
+            FixedStrategy(vec![
+                (date(2), Action::Buy(9.0)),
+                (date(4), Action::Sell(14.0)),
+            ]),
+            vec![],
+            &trades,
+        )
+        .unwrap();
+
+        let (first_buy_open, last_close) = (10.0, 13.0);
+        assert!((r.benchmark_roi - (last_close - first_buy_open) / first_buy_open).abs() < 1e-12);
+        assert_eq!(r.roi, 14.0 / 9.0);
+        // `roi` is a multiple of the invested amount, the benchmark a return
+        assert!(r.roi - 1.0 > r.benchmark_roi);
+
+        let idle = StrategyEvaluator {
+            config: StrategyEvaluatorConfig::default(),
+        }
+        .evaluate(
+            FixedStrategy(vec![(date(4), Action::Sell(14.0))]),
+            vec![],
+            &trades,
+        )
+        .unwrap();
+        assert_eq!(idle.benchmark_roi, 0.0);
+    }
+
+    #[test]
+    fn unittest_max_drawdown() {
+        let evaluator = StrategyEvaluator {
+            config: StrategyEvaluatorConfig::default(),
+        };
+        let buy_and_hold = || FixedStrategy(vec![(date(0), Action::Buy(10.0))]);
+
+        let rising = evaluator
+            .evaluate(
+                buy_and_hold(),
+                vec![],
+                &trades_from_closes(&[10.0, 11.0, 12.0, 13.0]),
+            )
+            .unwrap();
+        assert_eq!(rising.max_drawdown, 0.0);
+
+        // the trough falls on a bar without any action, so only marking every close catches it
+        let dip = evaluator
+            .evaluate(
+                buy_and_hold(),
+                vec![],
+                &trades_from_closes(&[10.0, 20.0, 10.0, 15.0]),
+            )
+            .unwrap();
+        assert_eq!(dip.max_drawdown, 0.5);
+    }
+
+    #[test]
+    fn unittest_blackout_ranges() {
+        let trades = trades_from_closes(&[10.0, 11.0, 12.0, 13.0, 14.0]);
+
+        let r = StrategyEvaluator {
+            config: StrategyEvaluatorConfig::default()
+                .with_blackout_ranges(vec![(date(1), date(3))]),
+        }
+        .evaluate(
+            FixedStrategy(vec![
+                (date(0), Action::Buy(10.0)),
+                (date(2), Action::Buy(12.0)),
+                (date(3), Action::Sell(13.0)),
+            ]),
+            vec![],
+            &trades,
+        )
+        .unwrap();
+
+        assert_eq!(
+            r.executions
+                .iter()
+                .map(|e| (e.date, e.action.is_buy()))
+                .collect::<Vec<_>>(),
+            vec![(date(0), true), (date(3), false)]
+        );
+        assert_eq!(r.stock, 0.0);
+    }
+
+    #[test]
+    fn unittest_compare_strategies() {
+        // a slide that keeps making new lows, then a bounce and a rally that fades
+        let closes = (0..15)
+            .map(|ix| 100.0 - 3.0 * ix as f64)
+            .chain((0..10).map(|ix| 58.0 + 4.0 * ix as f64))
+            .chain((0..10).map(|ix| 94.0 - 2.0 * ix as f64))
+            .collect::<Vec<_>>();
+        let trades = trades_from_closes(&closes);
+
+        let strategies = || -> Vec<(String, Box<dyn BuySellStrategy>)> {
+            vec![
+                (
+                    "naive".to_owned(),
+                    Box::new(NaiveStrategy {
+                        buy_move: 3,
+                        sell_move: 3,
+                        breakout_margin: 0.0,
+                    }),
+                ),
+                (
+                    "rsi".to_owned(),
+                    Box::new(RsiStrategy {
+                        period: 5,
+                        oversold: 30.0,
+                        overbought: 70.0,
+                    }),
+                ),
+            ]
+        };
+
+        for metric in [RankMetric::Roi, RankMetric::Sharpe, RankMetric::Calmar] {
+            let ranked = compare_strategies(
+                strategies(),
+                &[ConsecutiveBuyRemover {}.boxed()],
+                &trades,
+                StrategyEvaluatorConfig::default().with_rank_metric(metric),
+            )
+            .unwrap();
+
+            // naive buys into the slide and sells its low, rsi buys the bounce and sells the top
+            assert_eq!(
+                ranked
+                    .iter()
+                    .map(|(name, _)| name.as_str())
+                    .collect::<Vec<_>>(),
+                vec!["rsi", "naive"]
+            );
+            assert_eq!(ranked[0].1.roi, 90.0 / 66.0);
+            assert!(ranked[1].1.roi < 1.0);
+        }
+    }
+
+    #[test]
+    fn unittest_target_weights() {
+        let trades = trades_from_closes(&[100.0, 100.0, 105.0, 110.0, 120.0]);
+
+        let r = StrategyEvaluator {
+            config: StrategyEvaluatorConfig::default().with_initial_capital(1000.0),
+        }
+        .evaluate_weights(
+            SignalWeights(FixedStrategy(vec![
+                (date(0), Action::Sell(100.0)),
+                (date(1), Action::Buy(100.0)),
+                (date(3), Action::Sell(110.0)),
+            ])),
+            &trades,
+        )
+        .unwrap();
+
+        assert_eq!(
+            r.executions
+                .iter()
+                .map(|e| (e.date, e.action.is_buy(), e.shares))
+                .collect::<Vec<_>>(),
+            vec![(date(1), true, 10.0), (date(3), false, 10.0)]
+        );
+        assert_eq!(r.stock, 0.0);
+        assert_eq!(r.balance, 1100.0);
+    }
+
+    #[test]
+    fn unittest_initial_position() {
+        let trades = trades_from_closes(&[100.0, 120.0, 110.0]);
+
+        let r = StrategyEvaluator {
+            config: StrategyEvaluatorConfig::default().with_initial_position(10, 100.0),
+        }
+        .evaluate(
+            FixedStrategy(vec![(date(1), Action::Sell(120.0))]),
+            vec![],
+            &trades,
+        )
+        .unwrap();
+
+        assert_eq!(r.stock, 0.0);
+        assert_eq!(r.executions.len(), 1);
+        assert_eq!(r.executions[0].shares, 10.0);
+        assert_eq!(r.income, 1200.0);
+        assert!((r.roi - 1.2).abs() < 1e-9);
+    }
+
+    #[test]
+    fn unittest_action_serde() -> eyre::Result<()> {
+        let actions = vec![
+            (date(0), Action::Buy(123.0)),
+            (date(1), Action::Sell(130.5)),
+        ];
+
+        let json = serde_json::to_string(&actions)?;
+        assert_eq!(
+            json,
+            r#"[["2024-01-01",{"type":"Buy","price":123.0}],["2024-01-02",{"type":"Sell","price":130.5}]]"#
+        );
+
+        let parsed: Vec<(NaiveDate, Action)> = serde_json::from_str(&json)?;
+        assert_eq!(parsed, actions);
+        Ok(())
+    }
+
+    #[test]
+    #[should_panic(expected = "outside of")]
+    fn unittest_validate_fills() {
+        let trades = trades_from_closes(&[100.0, 110.0, 120.0]);
+
+        StrategyEvaluator {
+            config: StrategyEvaluatorConfig::default().with_validate_fills(true),
+        }
+        .evaluate(
+            FixedStrategy(vec![
+                (date(0), Action::Buy(100.0)),
+                (date(1), Action::Sell(150.0)),
+            ]),
+            vec![],
+            &trades,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn unittest_aggregate_results() {
+        let evaluator = StrategyEvaluator {
+            config: StrategyEvaluatorConfig::default(),
+        };
+        let hold = |code: &str, buy: f64, last: f64| {
+            let r = evaluator
+                .evaluate(
+                    FixedStrategy(vec![(date(0), Action::Buy(buy))]),
+                    vec![],
+                    &trades_from_closes(&[buy, last]),
+                )
+                .unwrap();
+            (code.to_owned(), r)
+        };
+
+        let summary = aggregate_results(&[
+            hold("A", 100.0, 120.0),
+            hold("B", 100.0, 90.0),
+            hold("C", 50.0, 100.0),
+        ]);
+
+        assert_eq!(summary.total_invested, 250.0);
+        assert!((summary.roi - 1.24).abs() < 1e-9);
+        assert!((summary.mean_roi - 4.1 / 3.0).abs() < 1e-9);
+        assert!((summary.median_roi - 1.2).abs() < 1e-9);
+        assert!((summary.profitable - 2.0 / 3.0).abs() < 1e-9);
+
+        let idle = evaluator
+            .evaluate(
+                FixedStrategy(vec![]),
+                vec![],
+                &trades_from_closes(&[100.0, 120.0]),
+            )
+            .unwrap();
+        let summary = aggregate_results(&[("D".to_owned(), idle)]);
+        assert_eq!(summary.total_invested, 0.0);
+        assert_eq!(summary.roi, 0.0);
+    }
+
+    #[test]
+    fn unittest_trend_pullback_strategy() {
+        // +1 a day with a 3 day pullback every 10 days, then a collapse
+        let mut close = 100.0;
+        let mut closes = Vec::new();
+        for ix in 0..60 {
+            close += if ix % 10 >= 7 { -0.5 } else { 1.0 };
+            closes.push(close);
+        }
+        for _ in 0..10 {
+            close -= 5.0;
+            closes.push(close);
+        }
+        let trades = trades_from_closes(&closes);
+
+        let strategy = TrendPullbackStrategy {
+            trend_period: 20,
+            pullback_period: 5,
+        };
+        let buys = strategy.buy(&trades);
+        let sells = strategy.sell(&trades);
+
+        let index = |actions: Vec<(NaiveDate, Action)>| {
+            actions
+                .into_iter()
+                .map(|(d, _)| (d - date(0)).num_days() as usize)
+                .collect::<Vec<_>>()
+        };
+        let buys = index(buys);
+        let sells = index(sells);
+
+        // entries only on pullback days of the uptrend, exits only during the collapse
+        assert!(!buys.is_empty());
+        assert!(buys.iter().all(|ix| ix % 10 >= 7 && *ix < 60));
+        assert!(!sells.is_empty());
+        assert!(sells.iter().all(|ix| *ix >= 60));
+    }
+
+    #[test]
+    fn unittest_random_slippage() {
+        let closes = (0..40)
+            .map(|ix| 100.0 + (ix % 7) as f64 * 3.0 - (ix % 3) as f64 * 4.0)
+            .collect::<Vec<_>>();
+        let trades = trades_from_closes(&closes);
+        let evaluate = |config: StrategyEvaluatorConfig| {
+            StrategyEvaluator { config }
+                .evaluate(
+                    NaiveStrategy {
+                        buy_move: 3,
+                        sell_move: 3,
+                        breakout_margin: 0.0,
+                    },
+                    vec![],
+                    &trades,
+                )
+                .unwrap()
+        };
+
+        let clean = evaluate(StrategyEvaluatorConfig::default());
+        let first = evaluate(StrategyEvaluatorConfig::default().with_random_slippage(50.0, 7));
+        let again = evaluate(StrategyEvaluatorConfig::default().with_random_slippage(50.0, 7));
+        let other = evaluate(StrategyEvaluatorConfig::default().with_random_slippage(50.0, 8));
+
+        assert!(clean.executions.len() > 2);
+        assert_eq!(first.roi, again.roi);
+        assert_ne!(first.roi, other.roi);
+        assert!(first.roi < clean.roi);
+    }
+
+    #[test]
+    fn unittest_backtest_market_pipelines() {
+        let actions = FixedStrategy(vec![
+            (date(0), Action::Buy(100.0)),
+            (date(1), Action::Sell(110.0)),
+        ]);
+        let stocks = [
+            Stock {
+                market: StockMarket::Kospi,
+                ..stock_from_closes("KOSPI", &[100.0, 110.0, 120.0])
+            },
+            Stock {
+                market: StockMarket::Nasdaq,
+                ..stock_from_closes("NASDAQ", &[100.0, 110.0, 120.0])
+            },
+        ];
+        let pipelines = [(StockMarket::Kospi, vec![NeverSellStrategy {}.boxed()])]
+            .into_iter()
+            .collect();
+
+        let results = backtest_market(
+            &stocks,
+            &actions,
+            &pipelines,
+            StrategyEvaluatorConfig::default(),
+        )
+        .unwrap();
+
+        assert_eq!(results[0].0, "KOSPI");
+        assert_eq!(results[0].1.stock, 1.0);
+        assert_eq!(results[1].0, "NASDAQ");
+        assert_eq!(results[1].1.stock, 0.0);
+    }
+
+    #[test]
+    fn unittest_winning_losing_streaks() {
+        // win, win, loss, loss, loss, win
+        let closes = [
+            100.0, 110.0, 100.0, 105.0, 100.0, 90.0, 100.0, 95.0, 100.0, 80.0, 100.0, 120.0,
+        ];
+        let trades = trades_from_closes(&closes);
+        let actions = closes
+            .iter()
+            .enumerate()
+            .map(|(ix, close)| {
+                let act = if ix % 2 == 0 {
+                    Action::Buy(*close)
+                } else {
+                    Action::Sell(*close)
+                };
+                (date(ix), act)
+            })
+            .collect();
+
+        let r = StrategyEvaluator {
+            config: StrategyEvaluatorConfig::default(),
+        }
+        .evaluate(FixedStrategy(actions), vec![], &trades)
+        .unwrap();
+
+        assert_eq!(r.max_winning_streak, 2);
+        assert_eq!(r.max_losing_streak, 3);
+    }
+
+    #[test]
+    fn unittest_vwap_execution() {
+        let bar = DaySeriesData {
+            open: 100.0,
+            high: 110.0,
+            low: 90.0,
+            close: 105.0,
+            volume: 1000,
+        };
+        let trades = [(date(0), bar)].into_iter().collect();
+        let evaluate = |execution| {
+            StrategyEvaluator {
+                config: StrategyEvaluatorConfig::default()
+                    .with_buy_sizing(BuySizing::Shares(500))
+                    .with_execution(execution),
+            }
+            .evaluate(
+                FixedStrategy(vec![(date(0), Action::Buy(bar.open))]),
+                vec![],
+                &trades,
+            )
+            .unwrap()
+        };
+
+        let open = evaluate(ExecutionModel::Signal);
+        let vwap = evaluate(ExecutionModel::Vwap { participation: 0.1 });
+
+        assert_eq!(open.stock, 500.0);
+        assert_eq!(open.invest, 50000.0);
+        assert_eq!(vwap.stock, 100.0);
+        assert!((vwap.invest / vwap.stock - 305.0 / 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn unittest_write_results_json() -> eyre::Result<()> {
+        let evaluator = StrategyEvaluator {
+            config: StrategyEvaluatorConfig::default(),
+        };
+        let trades = trades_from_closes(&[10.0, 11.0, 12.0]);
+        let results = [("A", 10.0), ("B", 11.0)]
+            .into_iter()
+            .map(|(code, buy)| {
+                let result = evaluator.evaluate(
+                    FixedStrategy(vec![(date(0), Action::Buy(buy))]),
+                    vec![],
+                    &trades,
+                )?;
+                Ok((code.to_owned(), result))
+            })
+            .collect::<error::Result<Vec<_>>>()?;
+
+        let mut buffer = Vec::new();
+        write_results_json(&mut buffer, &results)?;
+        let parsed: BTreeMap<String, StrategyEvaluatorResult> = serde_json::from_slice(&buffer)?;
+
+        assert_eq!(parsed.keys().collect::<Vec<_>>(), vec!["A", "B"]);
+        for (code, result) in &results {
+            // plain floats are parsed back to within an ulp, the lossless ones exactly
+            for ((name, restored), (_, value)) in
+                parsed[code].to_record().into_iter().zip(result.to_record())
+            {
+                assert!(
+                    restored == value || (restored - value).abs() <= 1e-12 * value.abs(),
+                    "{code} {name}: {restored} != {value}"
+                );
+            }
+            assert_eq!(parsed[code].executions.len(), result.executions.len());
+            assert_eq!(parsed[code].equity_curve, result.equity_curve);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn unittest_write_actions_csv() -> eyre::Result<()> {
+        let actions = vec![
+            (ymd(2024, 1, 2), Action::Buy(123.0)),
+            (ymd(2024, 1, 3), Action::Sell(130.55)),
+            (ymd(2024, 1, 4), Action::Short(0.1 + 0.2)),
+            (ymd(2024, 1, 5), Action::Cover(99.0)),
+        ];
+
+        let mut buffer = Vec::new();
+        write_actions_csv(&mut buffer, &actions)?;
+        let csv = String::from_utf8(buffer)?;
+        let mut lines = csv.lines();
+
+        assert_eq!(lines.next(), Some("date,action,price"));
+        assert_eq!(lines.clone().next(), Some("2024-01-02,BUY,123"));
+        let parsed = lines
+            .map(|line| {
+                let [date, action, price] = line.split(',').collect::<Vec<_>>()[..] else {
+                    panic!("{line}");
+                };
+                let price = price.parse::<f64>().unwrap();
+                let action = match action {
+                    "BUY" => Action::Buy(price),
+                    "SELL" => Action::Sell(price),
+                    "SHORT" => Action::Short(price),
+                    "COVER" => Action::Cover(price),
+                    _ => panic!("{action}"),
+                };
+                (date.parse::<NaiveDate>().unwrap(), action)
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(parsed, actions);
+        Ok(())
+    }
+
+    #[test]
+    fn unittest_write_records_csv() -> eyre::Result<()> {
+        let r = StrategyEvaluator {
+            config: StrategyEvaluatorConfig::default(),
+        }
+        .evaluate(
+            FixedStrategy(vec![
+                (date(0), Action::Buy(100.0)),
+                (date(1), Action::Sell(110.0)),
+            ]),
+            vec![],
+            &trades_from_closes(&[100.0, 110.0]),
+        )
+        .unwrap();
+
+        let mut buffer = Vec::new();
+        write_records_csv(
+            &mut buffer,
+            &[(vec![("buy_move".to_owned(), 20.0)], r.clone())],
+        )?;
+        let csv = String::from_utf8(buffer)?;
+        let lines = csv.lines().collect::<Vec<_>>();
+
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("buy_move,stock,trading,balance,invest,income,"));
+        assert_eq!(lines[0].split(',').count(), r.to_record().len() + 1);
+        assert!(lines[1].starts_with("20,0,2,10,100,110,"));
+        Ok(())
+    }
+
+    #[test]
+    fn unittest_opening_range_breakout_strategy() {
+        let bar = |low: f64, high: f64, close: f64| DaySeriesData {
+            open: close,
+            high,
+            low,
+            close,
+            volume: 1000,
+        };
+        let trades = [
+            bar(99.0, 101.0, 100.0),
+            bar(99.5, 101.5, 101.2),
+            bar(100.0, 104.0, 103.5),
+            bar(102.0, 104.0, 103.0),
+            bar(100.0, 103.0, 101.0),
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(ix, bar)| (date(ix), bar))
+        .collect();
+
+        let strategy = OpeningRangeBreakoutStrategy { range_pct: 0.01 };
+
+        // 101.2 only beats the prior high by 0.2%, 103.5 beats it by 2%
+        assert_eq!(strategy.buy(&trades), vec![(date(2), Action::Buy(103.5))]);
+        assert_eq!(strategy.sell(&trades), vec![(date(4), Action::Sell(101.0))]);
+    }
+
+    #[test]
+    fn unittest_weighted_blend_strategy() {
+        let trades = trades_from_closes(&[100.0; 4]);
+        let blend = |a: f64, b: f64| WeightedBlendStrategy {
+            members: vec![
+                (
+                    a,
+                    Box::new(FixedWeights(vec![(date(0), 0.0), (date(1), 1.0)])),
+                ),
+                (b, Box::new(FixedWeights(vec![(date(1), -1.0)]))),
+            ],
+        };
+
+        let opposing = blend(0.5, 0.5).weights(&trades);
+        assert_eq!(opposing.len(), 2);
+        assert!(opposing.iter().all(|(_, weight)| weight.abs() < 1e-9));
+
+        let tilted = blend(0.7, 0.3).weights(&trades);
+        assert!((tilted[1].1 - 0.4).abs() < 1e-9);
+    }
+
+    #[test]
+    fn unittest_pessimistic_execution() {
+        let trades = [100.0, 98.0, 104.0, 101.0, 97.0, 106.0, 103.0]
+            .into_iter()
+            .enumerate()
+            .map(|(ix, close)| {
+                let bar = DaySeriesData {
+                    open: close,
+                    high: close * 1.02,
+                    low: close * 0.98,
+                    close,
+                    volume: 1000,
+                };
+                (date(ix), bar)
+            })
+            .collect();
+        let evaluate = |execution| {
+            StrategyEvaluator {
+                config: StrategyEvaluatorConfig::default().with_execution(execution),
+            }
+            .evaluate(
+                NaiveStrategy {
+                    buy_move: 1,
+                    sell_move: 1,
+                    breakout_margin: 0.0,
+                },
+                vec![],
+                &trades,
+            )
+            .unwrap()
+        };
+
+        let normal = evaluate(ExecutionModel::Signal);
+        let pessimistic = evaluate(ExecutionModel::Pessimistic);
+
+        assert_eq!(normal.executions.len(), pessimistic.executions.len());
+        assert!(pessimistic.roi <= normal.roi);
+        assert_eq!(pessimistic.executions[0].action, Action::Buy(98.0 * 1.02));
+    }
+
+    #[test]
+    fn unittest_evaluate_empty_trades() {
+        let evaluator = StrategyEvaluator {
+            config: StrategyEvaluatorConfig::default(),
+        };
+        let trades = BTreeMap::new();
+
+        let r = evaluator.evaluate(FixedStrategy(vec![]), vec![], &trades);
+        assert!(matches!(r, Err(StockFilterError::EmptyTrades)));
+
+        let r = evaluator.evaluate_weights(FixedWeights(vec![]), &trades);
+        assert!(matches!(r, Err(StockFilterError::EmptyTrades)));
+    }
+
+    #[test]
+    fn unittest_monthly_contribution() {
+        let mut closes = vec![10.0; 90];
+        closes.push(11.0);
+        let trades = trades_from_closes(&closes);
+
+        let r = StrategyEvaluator {
+            config: StrategyEvaluatorConfig::default()
+                .with_initial_capital(1000.0)
+                .with_monthly_contribution(100.0),
+        }
+        .evaluate(
+            FixedStrategy(vec![(date(0), Action::Buy(10.0))]),
+            vec![],
+            &trades,
+        )
+        .unwrap();
+
+        // deposited on 02-01 and 03-01, 59 and 30 of the 90 days before the end
+        let base = 1000.0 + 100.0 * 59.0 / 90.0 + 100.0 * 30.0 / 90.0;
+        assert_eq!(r.total_contributions, 200.0);
+        assert_eq!(r.balance, 1000.0 - 10.0 + 200.0 + 11.0);
+        assert!((r.money_weighted_return - 1.0 / base).abs() < 1e-12);
+        assert!(r.irr > 0.0);
+        assert_eq!(r.equity_curve[&date(31)], 1000.0);
+        assert_eq!(r.equity_curve[&date(90)], 1001.0);
+    }
+
+    #[test]
+    fn unittest_log_metrics() {
+        let closes = (0..400)
+            .map(|ix| {
+                let price = 10.0 * 1.01f64.powi(ix);
+                if (200..260).contains(&ix) {
+                    price * 0.4
+                } else {
+                    price
+                }
+            })
+            .collect::<Vec<_>>();
+        let trades = trades_from_closes(&closes);
+        let evaluate = |log_metrics| {
+            StrategyEvaluator {
+                config: StrategyEvaluatorConfig::default().with_log_metrics(log_metrics),
+            }
+            .evaluate(
+                FixedStrategy(vec![(date(0), Action::Buy(10.0))]),
+                vec![],
+                &trades,
+            )
+            .unwrap()
+        };
+
+        let arithmetic = evaluate(false);
+        let log = evaluate(true);
+
+        let log_returns = metrics::log_returns(&arithmetic.equity_curve);
+        assert!((log.sharpe - metrics::sharpe(&log_returns)).abs() < 1e-12);
+        assert!(log.sharpe != arithmetic.sharpe);
+        assert!((log.max_drawdown + (1.0 - arithmetic.max_drawdown).ln()).abs() < 1e-12);
+        assert!(log.max_drawdown > arithmetic.max_drawdown);
+        assert!((log.cagr - arithmetic.cagr.ln_1p()).abs() < 1e-12);
+        assert_eq!(log.roi, arithmetic.roi);
+    }
+
+    #[test]
+    fn unittest_sharpe_risk_free_rate() {
+        let evaluate = |closes: &[Price], risk_free_rate| {
+            StrategyEvaluator {
+                config: StrategyEvaluatorConfig::default().with_risk_free_rate(risk_free_rate),
+            }
+            .evaluate(
+                FixedStrategy(vec![(date(0), Action::Buy(closes[0]))]),
+                vec![],
+                &trades_from_closes(closes),
+            )
+            .unwrap()
+        };
+        let rising = (0..100).map(|ix| 10.0 + ix as f64).collect::<Vec<_>>();
+
+        let r = evaluate(&rising, 0.0);
+        let returns = metrics::daily_returns(&r.equity_curve);
+        assert!(r.sharpe > 0.0);
+        assert!((r.sharpe - metrics::sharpe(&returns)).abs() < 1e-12);
+
+        let risk_free_rate = 0.01;
+        let excess = returns
+            .iter()
+            .map(|ret| ret - risk_free_rate)
+            .collect::<Vec<_>>();
+        let r = evaluate(&rising, risk_free_rate);
+        assert!((r.sharpe - metrics::sharpe(&excess)).abs() < 1e-12);
+        assert!(r.sharpe < evaluate(&rising, 0.0).sharpe);
+
+        // no variance in the returns
+        assert_eq!(evaluate(&[10.0; 20], risk_free_rate).sharpe, 0.0);
+    }
+
+    #[test]
+    fn unittest_hedged_returns() {
+        let pattern = [0.01, -0.02, 0.015, -0.005, 0.02];
+        let closes = (0..40)
+            .scan(100.0, |close, ix| {
+                *close *= 1.0 + pattern[ix % pattern.len()];
+                Some(*close)
+            })
+            .collect::<Vec<_>>();
+        let benchmark = stock_from_closes("INDEX", &closes);
+
+        // holding the benchmark itself is all beta
+        let r = StrategyEvaluator {
+            config: StrategyEvaluatorConfig::default(),
+        }
+        .evaluate(
+            FixedStrategy(vec![(date(0), Action::Buy(closes[0]))]),
+            vec![],
+            &benchmark.trades,
+        )
+        .unwrap();
+
+        let hedged = r.hedged_returns(&benchmark);
+        assert_eq!(hedged.len(), closes.len() - 1);
+        assert!(hedged.iter().all(|ret| ret.abs() < 1e-12));
+        assert!(r.sharpe > 0.0);
+    }
+
+    #[test]
+    fn unittest_backtest_report_json() {
+        let trades = trades_from_closes(&[10.0, 11.0, 12.0, 11.0, 13.0]);
+        let config =
+            StrategyEvaluatorConfig::default().with_commission(CommissionModel::per_share(0.1));
+        let result = StrategyEvaluator {
+            config: config.clone(),
+        }
+        .evaluate(
+            FixedStrategy(vec![
+                (date(0), Action::Buy(10.0)),
+                (date(2), Action::Sell(12.0)),
+                (date(3), Action::Buy(11.0)),
+            ]),
+            vec![],
+            &trades,
+        )
+        .unwrap();
+
+        let params = vec![("buy_move".to_owned(), 3.0)];
+        let json = BacktestReport::new(&config, params.clone(), result.clone())
+            .to_json()
+            .unwrap();
+        let report = BacktestReport::from_json(&json).unwrap();
+
+        assert_eq!(report.params, params);
+        assert!(report.config.max_leverage.is_infinite());
+        assert_eq!(report.trades.len(), 1);
+        assert_eq!(report.trades[0].pnl, 2.0);
+        assert_eq!(report.result.executions.len(), 3);
+        assert_eq!(report.result.roi, result.roi);
+        assert_eq!(report.result.calmar, result.calmar);
+        assert_eq!(report.result.equity_curve, result.equity_curve);
+        let fees = report
+            .metrics
+            .iter()
+            .find(|(name, _)| name == "fees")
+            .unwrap()
+            .1;
+        assert!((fees.unwrap() - 0.3).abs() < 1e-9);
+    }
+
+    struct InjectSell(NaiveDate, Price);
+
+    impl FoldStrategy for InjectSell {
+        fn fold(
+            &self,
+            mut actions: Vec<(NaiveDate, Action)>,
+            _: &BTreeMap<NaiveDate, DaySeriesData>,
+        ) -> Vec<(NaiveDate, Action)> {
+            actions.push((self.0, Action::Sell(self.1)));
+            actions
+        }
+    }
+
+    #[test]
+    fn unittest_same_day_policy() {
+        let trades = trades_from_closes(&[10.0; 6]);
+        let evaluate = |policy| {
+            StrategyEvaluator {
+                config: StrategyEvaluatorConfig::default().with_same_day_policy(policy),
+            }
+            .evaluate(
+                FixedStrategy(vec![(date(0), Action::Buy(10.0))]),
+                vec![
+                    InjectSell(date(3), 12.0).boxed(),
+                    InjectSell(date(3), 11.0).boxed(),
+                ],
+                &trades,
+            )
+            .unwrap()
+            .executions
+            .iter()
+            .map(|e| (e.date, e.action))
+            .collect::<Vec<_>>()
+        };
+
+        let sold_at = |price| vec![(date(0), Action::Buy(10.0)), (date(3), Action::Sell(price))];
+        assert_eq!(evaluate(SameDayPolicy::WorstPrice), sold_at(11.0));
+        assert_eq!(evaluate(SameDayPolicy::First), sold_at(12.0));
+        assert_eq!(evaluate(SameDayPolicy::KeepAll), sold_at(12.0));
+
+        let resolved = resolve_same_day(
+            vec![
+                (date(1), Action::Buy(10.0)),
+                (date(0), Action::Sell(9.0)),
+                (date(1), Action::Sell(11.0)),
+                (date(1), Action::Buy(10.5)),
+            ],
+            SameDayPolicy::WorstPrice,
+        );
+        assert_eq!(
+            resolved,
+            vec![
+                (date(0), Action::Sell(9.0)),
+                (date(1), Action::Buy(10.5)),
+                (date(1), Action::Sell(11.0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn unittest_max_trades() {
+        let trades = trades_from_closes(&(0..20).map(|ix| 10.0 + ix as f64).collect::<Vec<_>>());
+        let actions = (0..10)
+            .map(|ix| {
+                let price = 10.0 + (2 * ix) as f64;
+                if ix % 2 == 0 {
+                    (date(2 * ix), Action::Buy(price))
+                } else {
+                    (date(2 * ix), Action::Sell(price))
+                }
+            })
+            .collect::<Vec<_>>();
+        let evaluate = |config: StrategyEvaluatorConfig| {
+            StrategyEvaluator { config }
+                .evaluate(FixedStrategy(actions.clone()), vec![], &trades)
+                .unwrap()
+        };
+
+        let capped = evaluate(StrategyEvaluatorConfig::default().with_max_trades(3));
+        assert_eq!(capped.executions.len(), 3);
+        assert_eq!(capped.equity_curve.last_key_value().unwrap().0, &date(4));
+        assert_eq!(capped.stock, 1.0);
+
+        let liquidated = evaluate(
+            StrategyEvaluatorConfig::default()
+                .with_max_trades(3)
+                .with_liquidate_at_end(true),
+        );
+        assert_eq!(liquidated.executions.len(), 4);
+        assert_eq!(liquidated.stock, 0.0);
+        assert_eq!(liquidated.executions[3].date, date(4));
+        assert_eq!(liquidated.executions[3].action, Action::Sell(14.0));
+
+        assert_eq!(
+            evaluate(StrategyEvaluatorConfig::default())
+                .executions
+                .len(),
+            10
+        );
+    }
+
+    #[test]
+    fn unittest_naive_strategy_breakout_margin() {
+        let opens = [100.0, 100.0, 100.0, 99.5, 100.0, 98.0, 100.5, 102.0];
+        let trades = opens
+            .iter()
+            .enumerate()
+            .map(|(ix, open)| {
+                let mut data = day(100.0);
+                data.open = *open;
+                data.low = data.low.min(*open);
+                data.high = data.high.max(*open);
+                (date(ix), data)
+            })
+            .collect::<BTreeMap<_, _>>();
+        let strategy = |breakout_margin| NaiveStrategy {
+            buy_move: 3,
+            sell_move: 3,
+            breakout_margin,
+        };
+
+        assert_eq!(
+            strategy(0.01).buy_sell(&trades),
+            vec![(date(5), Action::Buy(98.0)), (date(7), Action::Sell(102.0))]
+        );
+        assert_eq!(
+            strategy(0.0).buy_sell(&trades),
+            vec![
+                (date(3), Action::Buy(99.5)),
+                (date(5), Action::Buy(98.0)),
+                (date(6), Action::Sell(100.5)),
+                (date(7), Action::Sell(102.0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn unittest_daily_loss_limit_fold() {
+        // buys every day below the previous close, the crash of day 3 loses more than 10% of
+        // the 196.0 of equity the day started with
+        let trades = trades_from_closes(&[100.0, 99.0, 98.0, 80.0, 79.0, 78.0]);
+        let actions = NaiveStrategy {
+            buy_move: 1,
+            sell_move: 1,
+            breakout_margin: 0.0,
+        }
+        .buy_sell(&trades);
+        assert_eq!(
+            actions.iter().map(|(date, _)| *date).collect::<Vec<_>>(),
+            (1..6).map(date).collect::<Vec<_>>()
+        );
+        let fold = |limit_pct| DailyLossLimitFold { limit_pct }.fold(actions.clone(), &trades);
 
-        Ok(())
+        assert_eq!(
+            fold(0.1),
+            vec![
+                (date(1), Action::Buy(99.0)),
+                (date(2), Action::Buy(98.0)),
+                (date(3), Action::Buy(80.0)),
+                (date(5), Action::Buy(78.0)),
+            ]
+        );
+        assert_eq!(fold(0.2), actions);
+    }
+
+    #[test]
+    fn unittest_gross_roi() {
+        let closes = (0..40)
+            .map(|ix| if ix % 2 == 0 { 10.0 } else { 10.2 })
+            .collect::<Vec<_>>();
+        let trades = trades_from_closes(&closes);
+        let actions = (0..40)
+            .map(|ix| match ix % 2 {
+                0 => (date(ix), Action::Buy(10.0)),
+                _ => (date(ix), Action::Sell(10.2)),
+            })
+            .collect::<Vec<_>>();
+
+        let r = StrategyEvaluator {
+            config: StrategyEvaluatorConfig::default()
+                .with_commission(CommissionModel::per_share(0.1)),
+        }
+        .evaluate(FixedStrategy(actions), vec![], &trades)
+        .unwrap();
+
+        assert!((r.gross_roi - 1.02).abs() < 1e-9);
+        assert!((r.roi - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn unittest_fill_fn() {
+        let trades = (0..4)
+            .map(|ix| {
+                let mut data = day(100.0 + ix as f64);
+                data.low = 95.0 + ix as f64;
+                (date(ix), data)
+            })
+            .collect::<BTreeMap<_, _>>();
+
+        let r = StrategyEvaluator {
+            config: StrategyEvaluatorConfig::default()
+                .with_validate_fills(true)
+                .with_fill_fn(|bar, _| bar.low),
+        }
+        .evaluate(
+            FixedStrategy(vec![
+                (date(0), Action::Buy(100.0)),
+                (date(2), Action::Sell(102.0)),
+            ]),
+            vec![],
+            &trades,
+        )
+        .unwrap();
+
+        assert_eq!(
+            r.executions.iter().map(|e| e.action).collect::<Vec<_>>(),
+            vec![Action::Buy(95.0), Action::Sell(97.0)]
+        );
+    }
+
+    #[test]
+    fn unittest_trade_return_histogram() {
+        let trades = trades_from_closes(&[100.0; 8]);
+        let round_trip = |ix: usize, sell| {
+            [
+                (date(2 * ix), Action::Buy(100.0)),
+                (date(2 * ix + 1), Action::Sell(sell)),
+            ]
+        };
+        let actions = [
+            round_trip(0, 106.0),
+            round_trip(1, 97.0),
+            round_trip(2, 108.0),
+            round_trip(3, 112.0),
+        ]
+        .concat();
+
+        let r = StrategyEvaluator {
+            config: StrategyEvaluatorConfig::default(),
+        }
+        .evaluate(FixedStrategy(actions), vec![], &trades)
+        .unwrap();
+
+        assert_eq!(
+            r.trade_return_histogram(0.05),
+            vec![(-0.05, 1), (0.05, 2), (0.1, 1)]
+        );
+
+        let r = StrategyEvaluator {
+            config: StrategyEvaluatorConfig::default().with_initial_position(1, 100.0),
+        }
+        .evaluate(
+            FixedStrategy(vec![(date(1), Action::Sell(108.0))]),
+            vec![],
+            &trades,
+        )
+        .unwrap();
+
+        assert_eq!(r.realized_pnl, 8.0);
+        assert_eq!(r.trade_return_histogram(0.05), vec![(0.05, 1)]);
+    }
+
+    #[test]
+    fn unittest_skip_first_n_signals() {
+        let trades = trades_from_closes(&[10.0, 11.0, 12.0, 11.0, 13.0, 15.0]);
+        let evaluate = |skip| {
+            StrategyEvaluator {
+                config: StrategyEvaluatorConfig::default().with_skip_first_n_signals(skip),
+            }
+            .evaluate(
+                FixedStrategy(vec![
+                    (date(0), Action::Buy(10.0)),
+                    (date(2), Action::Sell(12.0)),
+                    (date(3), Action::Buy(11.0)),
+                    (date(5), Action::Sell(15.0)),
+                ]),
+                vec![],
+                &trades,
+            )
+            .unwrap()
+        };
+
+        let all = evaluate(0);
+        let skipped = evaluate(1);
+
+        assert_eq!(all.executions[0].date, date(0));
+        assert_eq!(skipped.executions[0].date, date(3));
+        assert_eq!(skipped.executions.len(), 2);
+        assert!((all.roi - 27.0 / 21.0).abs() < 1e-12);
+        assert!((skipped.roi - 15.0 / 11.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn unittest_naive_strategy_sell_window() {
+        let trades = trades_from_closes(&(0..20).map(|ix| 100.0 + ix as f64).collect::<Vec<_>>());
+        let first_sell = |buy_move, sell_move| {
+            NaiveStrategy {
+                buy_move,
+                sell_move,
+                breakout_margin: 0.0,
+            }
+            .sell(&trades)
+            .first()
+            .map(|(date, _)| *date)
+        };
+
+        assert_eq!(first_sell(2, 5), Some(date(5)));
+        assert_eq!(first_sell(5, 2), Some(date(2)));
+        assert_ne!(first_sell(2, 5), first_sell(2, 2));
+    }
+
+    #[test]
+    fn unittest_evaluate_from_state() {
+        let closes = (0..30)
+            .map(|ix| 100.0 + ((ix * 7) % 11) as f64)
+            .collect::<Vec<_>>();
+        let trades = trades_from_closes(&closes);
+        let strategy = FixedStrategy(vec![
+            (date(2), Action::Buy(closes[2])),
+            (date(6), Action::Buy(closes[6])),
+            (date(12), Action::Sell(closes[12])),
+            (date(13), Action::Buy(closes[13])),
+            (date(25), Action::Sell(closes[25])),
+        ]);
+        let evaluator = StrategyEvaluator {
+            config: StrategyEvaluatorConfig::default()
+                .with_initial_capital(1000.0)
+                .with_buy_sizing(BuySizing::CashPercent(0.5))
+                .with_commission(CommissionModel::percentage(0.001)),
+        };
+
+        let full = evaluator.evaluate_with(&strategy, &[], &trades).unwrap();
+
+        let head = trades
+            .range(..date(15))
+            .map(|(day, data)| (*day, *data))
+            .collect();
+        let (_, state) = evaluator
+            .evaluate_from(&strategy, &[], &head, date(0), None)
+            .unwrap();
+        assert!(state.position() > 0.0);
+        let (chained, state) = evaluator
+            .evaluate_from(&strategy, &[], &trades, date(15), Some(state))
+            .unwrap();
+
+        assert_eq!(chained.to_record(), full.to_record());
+        assert_eq!(chained.equity_curve, full.equity_curve);
+        assert_eq!(state.position(), full.stock);
+    }
+
+    #[test]
+    fn unittest_evaluate_from_windowed_strategy() {
+        let closes = (0..60)
+            .map(|ix| 100.0 + 10.0 * (ix as f64 / 4.0).sin())
+            .collect::<Vec<_>>();
+        let trades = trades_from_closes(&closes);
+        let strategy = NaiveStrategy {
+            buy_move: 5,
+            sell_move: 5,
+            breakout_margin: 0.0,
+        };
+        let evaluator = StrategyEvaluator {
+            config: StrategyEvaluatorConfig::default(),
+        };
+
+        let fills = |r: &StrategyEvaluatorResult| {
+            r.executions
+                .iter()
+                .map(|e| (e.date, e.action.price(), e.shares))
+                .collect::<Vec<_>>()
+        };
+
+        let full = evaluator.evaluate_with(&strategy, &[], &trades).unwrap();
+        assert!(full.executions.iter().any(|e| e.date < date(30)));
+        assert!(full.executions.iter().any(|e| e.date >= date(30)));
+
+        let (head, tail): (BTreeMap<_, _>, BTreeMap<_, _>) =
+            trades.iter().partition(|(day, _)| **day < date(30));
+        let (_, state) = evaluator
+            .evaluate_from(&strategy, &[], &head, date(0), None)
+            .unwrap();
+        let (chained, _) = evaluator
+            .evaluate_from(&strategy, &[], &trades, date(30), Some(state.clone()))
+            .unwrap();
+        assert_eq!(chained.to_record(), full.to_record());
+        assert_eq!(fills(&chained), fills(&full));
+
+        // without the history the strategy warms up again and misses the signals of the
+        // first days of the segment
+        let (cold, _) = evaluator
+            .evaluate_from(&strategy, &[], &tail, date(30), Some(state))
+            .unwrap();
+        assert_ne!(fills(&cold), fills(&full));
+    }
+
+    #[test]
+    fn unittest_realized_unrealized_pnl() {
+        let trades = trades_from_closes(&[10.0, 12.0, 15.0, 14.0]);
+        let r = StrategyEvaluator {
+            config: StrategyEvaluatorConfig::default(),
+        }
+        .evaluate(
+            FixedStrategy(vec![
+                (date(0), Action::Buy(10.0)),
+                (date(1), Action::Buy(12.0)),
+            ]),
+            vec![],
+            &trades,
+        )
+        .unwrap();
+        assert_eq!((r.realized_pnl, r.unrealized_pnl), (0.0, 4.0 + 2.0));
+
+        let r = StrategyEvaluator {
+            config: StrategyEvaluatorConfig::default().with_sell_factor(0.5),
+        }
+        .evaluate(
+            FixedStrategy(vec![
+                (date(0), Action::Buy(10.0)),
+                (date(1), Action::Buy(12.0)),
+                (date(2), Action::Sell(15.0)),
+            ]),
+            vec![],
+            &trades,
+        )
+        .unwrap();
+
+        // the 10.0 lot closes at 15.0, the 12.0 lot stays open at 14.0
+        assert_eq!(r.realized_pnl, 5.0);
+        assert_eq!(r.unrealized_pnl, 2.0);
+        assert_eq!(open_lots(&r.executions), vec![(date(1), 12.0, 1.0)]);
+        assert_eq!(r.balance - r.stock * 14.0, -10.0 - 12.0 + 15.0);
+    }
+
+    #[test]
+    fn unittest_invert_fold() {
+        let closes = (0..60)
+            .map(|ix| 100.0 + ((ix * 13) % 17) as f64)
+            .collect::<Vec<_>>();
+        let trades = trades_from_closes(&closes);
+        let actions = NaiveStrategy {
+            buy_move: 5,
+            sell_move: 5,
+            breakout_margin: 0.0,
+        }
+        .buy_sell(&trades);
+        assert!(actions.iter().any(|(_, act)| act.is_buy()));
+
+        let inverted = InvertFold {}.fold(actions.clone(), &trades);
+
+        assert_eq!(inverted.len(), actions.len());
+        for ((date, act), (inverted_date, inverted_act)) in actions.iter().zip(&inverted) {
+            assert_eq!(date, inverted_date);
+            assert_eq!(act.is_buy(), inverted_act.is_sell());
+        }
+        assert_eq!(InvertFold {}.fold(inverted, &trades), actions);
+    }
+
+    #[test]
+    fn unittest_consecutive_sell_remover() {
+        let trades = trades_from_closes(&[10.0; 6]);
+        let actions = vec![
+            (date(0), Action::Buy(10.0)),
+            (date(1), Action::Sell(11.0)),
+            (date(2), Action::Sell(12.0)),
+            (date(3), Action::Sell(13.0)),
+            (date(4), Action::Buy(10.0)),
+            (date(5), Action::Buy(9.0)),
+        ];
+
+        let folded = ConsecutiveSellRemover {}.fold(actions, &trades);
+
+        assert_eq!(
+            folded,
+            vec![
+                (date(0), Action::Buy(10.0)),
+                (date(3), Action::Sell(13.0)),
+                (date(4), Action::Buy(10.0)),
+                (date(5), Action::Buy(9.0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn unittest_cooldown_fold() {
+        let trades = trades_from_closes(&[10.0; 20]);
+        let actions = vec![
+            (date(0), Action::Buy(10.0)),
+            (date(2), Action::Sell(11.0)),
+            (date(3), Action::Buy(10.0)),
+            (date(13), Action::Buy(10.0)),
+        ];
+
+        let folded = CooldownFold { min_days: 5 }.fold(actions, &trades);
+
+        assert_eq!(
+            folded,
+            vec![
+                (date(0), Action::Buy(10.0)),
+                (date(2), Action::Sell(11.0)),
+                (date(13), Action::Buy(10.0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn unittest_trailing_stop_fold() {
+        // 30% up to 130, then down 11.5% to 115
+        let trades = trades_from_closes(&[100.0, 110.0, 120.0, 130.0, 125.0, 118.0, 115.0, 114.0]);
+        let actions = vec![(date(0), Action::Buy(100.0))];
+
+        let folded = TrailingStopFold { pct: 0.1 }.fold(actions.clone(), &trades);
+        assert_eq!(
+            folded,
+            vec![
+                (date(0), Action::Buy(100.0)),
+                (date(6), Action::Sell(115.0))
+            ]
+        );
+
+        let folded = TrailingStopFold { pct: 0.2 }.fold(actions.clone(), &trades);
+        assert_eq!(folded, actions);
+    }
+
+    #[test]
+    fn unittest_take_profit_fold() {
+        let trades = trades_from_closes(&[100.0, 110.0, 118.0, 121.0, 125.0, 90.0, 115.0]);
+        let actions = vec![(date(0), Action::Buy(100.0)), (date(5), Action::Buy(90.0))];
+
+        let folded = TakeProfitFold { pct: 0.2 }.fold(actions, &trades);
+
+        assert_eq!(
+            folded,
+            vec![
+                (date(0), Action::Buy(100.0)),
+                (date(3), Action::Sell(121.0)),
+                (date(5), Action::Buy(90.0)),
+                (date(6), Action::Sell(115.0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn unittest_cash_buy_sizing() {
+        let evaluator = StrategyEvaluator {
+            config: StrategyEvaluatorConfig::default()
+                .with_initial_capital(5000.0)
+                .with_buy_sizing(BuySizing::Cash(1000.0)),
+        };
+
+        let r = evaluator
+            .evaluate(
+                FixedStrategy(vec![(date(0), Action::Buy(250.0))]),
+                vec![],
+                &trades_from_closes(&[250.0, 260.0]),
+            )
+            .unwrap();
+        assert_eq!(r.stock, 4.0);
+        assert_eq!(r.executions[0].shares, 4.0);
+
+        // a fractional position is sold whole
+        let r = evaluator
+            .evaluate(
+                FixedStrategy(vec![
+                    (date(0), Action::Buy(300.0)),
+                    (date(1), Action::Sell(330.0)),
+                ]),
+                vec![],
+                &trades_from_closes(&[300.0, 330.0]),
+            )
+            .unwrap();
+        assert!((r.executions[0].shares - 1000.0 / 300.0).abs() < 1e-12);
+        assert_eq!(r.stock, 0.0);
+        assert!((r.balance - 5100.0).abs() < 1e-9);
+        assert!((r.realized_pnl - 100.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn unittest_loss_sell_remover() {
+        let trades = trades_from_closes(&[10.0; 8]);
+        let actions = vec![
+            (date(0), Action::Buy(10.0)),
+            (date(1), Action::Buy(20.0)),
+            // below the average of 15
+            (date(2), Action::Sell(14.0)),
+            // below the highest buy but above the average
+            (date(3), Action::Sell(16.0)),
+            (date(4), Action::Buy(30.0)),
+            (date(5), Action::Sell(25.0)),
+            (date(6), Action::Sell(31.0)),
+        ];
+
+        let folded = LossSellRemover {}.fold(actions, &trades);
+
+        assert_eq!(
+            folded,
+            vec![
+                (date(0), Action::Buy(10.0)),
+                (date(1), Action::Buy(20.0)),
+                (date(3), Action::Sell(16.0)),
+                (date(4), Action::Buy(30.0)),
+                (date(6), Action::Sell(31.0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn unittest_evaluate_without_buys() {
+        let trades = trades_from_closes(&[10.0, 11.0, 12.0]);
+        let evaluator = StrategyEvaluator {
+            config: StrategyEvaluatorConfig::default().with_initial_capital(1000.0),
+        };
+
+        for actions in [vec![], vec![(date(1), Action::Sell(11.0))]] {
+            let r = evaluator
+                .evaluate(FixedStrategy(actions), vec![], &trades)
+                .unwrap();
+
+            assert_eq!(r.invest, 0.0);
+            assert_eq!(r.roi, 0.0);
+            assert_eq!(r.gross_roi, 0.0);
+            assert!(!r.has_trades());
+            assert_eq!(r.stock, 0.0);
+            assert!(r.executions.is_empty());
+            assert_eq!(r.balance, 1000.0);
+        }
+    }
+
+    #[test]
+    fn unittest_stoploss() {
+        let trades = trades_from_closes(&[100.0, 95.0, 89.0, 80.0, 90.0]);
+        let evaluate = |config: StrategyEvaluatorConfig| {
+            StrategyEvaluator { config }
+                .evaluate(
+                    FixedStrategy(vec![
+                        (date(0), Action::Buy(100.0)),
+                        (date(1), Action::Buy(95.0)),
+                    ]),
+                    vec![],
+                    &trades,
+                )
+                .unwrap()
+        };
+
+        // 89 is 8.7% below the average cost of 97.5, 80 is 18%
+        let r = evaluate(StrategyEvaluatorConfig::default().with_stoploss(0.1));
+        assert_eq!(r.stock, 0.0);
+        assert_eq!(r.executions.len(), 3);
+        assert_eq!(r.executions[2].date, date(3));
+        assert_eq!(r.executions[2].action, Action::Sell(80.0));
+        assert_eq!(r.realized_pnl, -35.0);
+
+        let r = evaluate(StrategyEvaluatorConfig::default());
+        assert_eq!(r.stock, 2.0);
+    }
+
+    #[test]
+    fn unittest_win_rate_and_profit_factor() {
+        let trades = trades_from_closes(&[100.0, 120.0, 110.0, 105.0, 100.0, 110.0]);
+        let r = StrategyEvaluator {
+            config: StrategyEvaluatorConfig::default(),
+        }
+        .evaluate(
+            FixedStrategy(vec![
+                (date(0), Action::Buy(100.0)),
+                (date(1), Action::Sell(120.0)),
+                (date(2), Action::Buy(110.0)),
+                (date(3), Action::Sell(105.0)),
+                (date(4), Action::Buy(100.0)),
+                (date(5), Action::Sell(110.0)),
+            ]),
+            vec![],
+            &trades,
+        )
+        .unwrap();
+
+        // +20 and +10 against -5
+        assert_eq!(r.win_rate, 2.0 / 3.0);
+        assert_eq!(r.profit_factor, 6.0);
+    }
+
+    #[test]
+    fn unittest_trade_log() {
+        let trades = trades_from_closes(&[100.0, 110.0, 120.0]);
+        let strategy = || {
+            FixedStrategy(vec![
+                (date(0), Action::Buy(100.0)),
+                (date(1), Action::Buy(110.0)),
+                (date(2), Action::Sell(120.0)),
+            ])
+        };
+        let evaluate = |config: StrategyEvaluatorConfig| {
+            StrategyEvaluator { config }
+                .evaluate(strategy(), vec![], &trades)
+                .unwrap()
+        };
+
+        let r = evaluate(StrategyEvaluatorConfig::default().with_trade_log(true));
+        // the sell of both shares closes the oldest lot first
+        let log = r
+            .trades_log
+            .iter()
+            .map(|t| {
+                (
+                    t.buy_date,
+                    t.buy_price,
+                    t.sell_date,
+                    t.sell_price,
+                    t.shares,
+                    t.pnl,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            log,
+            vec![
+                (date(0), 100.0, date(2), 120.0, 1.0, 20.0),
+                (date(1), 110.0, date(2), 120.0, 1.0, 10.0),
+            ]
+        );
+        assert_eq!(r.realized_pnl, 30.0);
+
+        assert!(evaluate(StrategyEvaluatorConfig::default())
+            .trades_log
+            .is_empty());
+    }
+
+    #[test]
+    fn unittest_short_cover() {
+        let trades = trades_from_closes(&[100.0, 95.0, 90.0, 80.0, 85.0]);
+        let evaluator = StrategyEvaluator {
+            config: StrategyEvaluatorConfig::default().with_initial_capital(1000.0),
+        };
+
+        let r = evaluator
+            .evaluate(
+                FixedStrategy(vec![
+                    (date(0), Action::Short(100.0)),
+                    (date(3), Action::Cover(80.0)),
+                ]),
+                vec![],
+                &trades,
+            )
+            .unwrap();
+
+        assert_eq!(r.short, 0.0);
+        assert_eq!(r.trading, 2.0);
+        assert_eq!(r.realized_pnl, 20.0);
+        assert_eq!(r.unrealized_pnl, 0.0);
+        assert_eq!(r.balance, 1020.0);
+        assert_eq!(r.roi, 1.2);
+        assert_eq!(round_trips(&r.executions)[0].pnl, 20.0);
+        assert_eq!(r.equity_curve[&date(2)], 1010.0);
+
+        // an open short is marked to the last close
+        let r = evaluator
+            .evaluate(
+                FixedStrategy(vec![(date(0), Action::Short(100.0))]),
+                vec![],
+                &trades,
+            )
+            .unwrap();
+
+        assert_eq!(r.short, 1.0);
+        assert_eq!(r.realized_pnl, 0.0);
+        assert_eq!(r.unrealized_pnl, 15.0);
+        assert_eq!(r.balance, 1015.0);
+        assert_eq!(r.roi, 1.15);
+    }
+
+    #[test]
+    fn unittest_back_tester() {
+        struct BuyUntil(NaiveDate);
+
+        impl LinearBuySellStrategy for BuyUntil {
+            fn buy(&self, _: &Account, stock: &StockInfo) -> bool {
+                stock.date < self.0
+            }
+
+            fn sell(&self, _: &Account, stock: &StockInfo) -> bool {
+                stock.date >= self.0
+            }
+        }
+
+        let stocks = [
+            stock_from_closes("A", &[10.0, 11.0, 12.0, 15.0]),
+            stock_from_closes("B", &[100.0, 100.0, 100.0, 100.0]),
+        ];
+
+        // A on days 0 and 1, nothing left for B or A on day 2
+        let r = BackTester {}.run(&BuyUntil(date(3)), &stocks, 25.0);
+
+        assert_eq!(r.skipped_buys, 4);
+        assert!(r.account.stocks.is_empty());
+        assert_eq!(r.account.balance, 25.0 - 10.0 - 11.0 + 2.0 * 15.0);
+        assert_eq!(r.realized_pnl, 2.0 * 15.0 - 10.0 - 11.0);
+
+        let r = BackTester {}.run(&BuyUntil(date(10)), &stocks, 125.0);
+        assert_eq!(r.account.stocks["A"], 2);
+        assert_eq!(r.account.stocks["B"], 1);
+        assert_eq!(r.account.balance, 125.0 - 10.0 - 100.0 - 11.0);
+        assert_eq!(r.skipped_buys, 5);
+        assert_eq!(r.realized_pnl, 0.0);
+    }
+
+    #[test]
+    fn unittest_commission_rate() {
+        let trades = trades_from_closes(&[50.0, 55.0, 60.0]);
+        let evaluate = |config: StrategyEvaluatorConfig| {
+            StrategyEvaluator {
+                config: config.with_buy_sizing(BuySizing::Shares(100)),
+            }
+            .evaluate(
+                FixedStrategy(vec![
+                    (date(0), Action::Buy(50.0)),
+                    (date(2), Action::Sell(60.0)),
+                ]),
+                vec![],
+                &trades,
+            )
+            .unwrap()
+        };
+
+        let free = evaluate(StrategyEvaluatorConfig::default());
+        let charged = evaluate(StrategyEvaluatorConfig::default().with_commission_rate(0.001));
+        let floored = evaluate(
+            StrategyEvaluatorConfig::default()
+                .with_commission_rate(0.001)
+                .with_commission_min(8.0),
+        );
+
+        assert_eq!(free.fees, 0.0);
+        assert!((charged.fees - (5.0 + 6.0)).abs() < 1e-9);
+        assert!((floored.fees - (8.0 + 8.0)).abs() < 1e-9);
+        assert_eq!(charged.invest, free.invest);
+        assert_eq!(charged.income, free.income);
+        assert!((free.roi - 6000.0 / 5000.0).abs() < 1e-12);
+        assert!((charged.roi - (6000.0 - 11.0) / 5000.0).abs() < 1e-12);
+        assert!((charged.balance - (free.balance - 11.0)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn unittest_rsi_strategy() {
+        // rsi(2): -, -, 0, 0, 66.7, 85.7, 93.3, 60.9, 25.4
+        let trades = trades_from_closes(&[10.0, 9.0, 8.0, 7.0, 9.0, 11.0, 13.0, 12.0, 10.0]);
+
+        let actions = RsiStrategy {
+            period: 2,
+            oversold: 30.0,
+            overbought: 70.0,
+        }
+        .buy_sell(&trades);
+
+        assert_eq!(
+            actions,
+            vec![(date(4), Action::Buy(9.0)), (date(7), Action::Sell(12.0))]
+        );
+    }
+
+    #[test]
+    fn unittest_macd_strategy() {
+        let closes = (0..120)
+            .map(|ix| 100.0 + 10.0 * (ix as f64 / 8.0).sin())
+            .collect::<Vec<_>>();
+        let trades = trades_from_closes(&closes);
+        let strategy = MacdStrategy::default();
+
+        let actions = strategy.buy_sell(&trades);
+        assert!(actions.iter().any(|(_, act)| act.is_buy()));
+        assert!(actions.iter().any(|(_, act)| act.is_sell()));
+
+        // the first signal line value is on day slow + signal - 1, a cross needs one more
+        let warmup = strategy.slow + strategy.signal - 1;
+        assert!(actions.iter().all(|(day, _)| *day >= date(warmup)));
+        let head = trades_from_closes(&closes[..warmup]);
+        assert!(strategy.buy_sell(&head).is_empty());
+    }
+
+    #[test]
+    fn unittest_bollinger_strategy() {
+        let strategy = BollingerStrategy {
+            period: 20,
+            num_std: 2.0,
+        };
+
+        let mut closes = vec![100.0; 60];
+        closes[30] = 120.0;
+        let actions = strategy.buy_sell(&trades_from_closes(&closes));
+        assert_eq!(actions, vec![(date(30), Action::Sell(120.0))]);
+
+        // no bands before `period` bars
+        let mut closes = vec![100.0; 10];
+        closes[5] = 120.0;
+        assert!(strategy.buy_sell(&trades_from_closes(&closes)).is_empty());
+    }
+
+    #[test]
+    fn unittest_atr_stop_strategy() {
+        let strategy = AtrStopStrategy {
+            atr_period: 5,
+            multiple: 2.0,
+        };
+
+        // bars one wide, level shifts of more than two true ranges up on day 10 and down on
+        // day 20. the shift on day 2 comes before the first atr
+        let closes = (0..30)
+            .map(|ix| match ix {
+                0..=1 => 97.0,
+                2..=9 => 100.0,
+                10..=19 => 105.0,
+                _ => 98.0,
+            })
+            .collect::<Vec<_>>();
+        let mut trades = trades_from_closes(&closes);
+        for bar in trades.values_mut() {
+            bar.high = bar.close + 0.5;
+            bar.low = bar.close - 0.5;
+        }
+
+        assert_eq!(
+            strategy.buy_sell(&trades),
+            vec![
+                (date(10), Action::Buy(105.0)),
+                (date(20), Action::Sell(98.0))
+            ]
+        );
+    }
+
+    #[test]
+    fn unittest_fold_pipeline() {
+        let trades = trades_from_closes(&[10.0, 12.0, 11.0, 9.0, 13.0]);
+        let actions = vec![
+            (date(0), Action::Buy(10.0)),
+            (date(1), Action::Buy(12.0)),
+            (date(2), Action::Sell(11.0)),
+            (date(3), Action::Buy(9.0)),
+            (date(4), Action::Sell(13.0)),
+        ];
+        let manual: Vec<Box<dyn FoldStrategy>> = vec![
+            InvertFold {}.boxed(),
+            InvertFold {}.boxed(),
+            ConsecutiveBuyRemover {}.boxed(),
+            LossSellRemover {}.boxed(),
+        ];
+        let manual = manual.iter().fold(actions.clone(), |actions, folder| {
+            folder.fold(actions, &trades)
+        });
+
+        // added in the wrong order, the risk fold still runs after the dedup
+        let pipeline = FoldPipeline::new()
+            .then(LossSellRemover {})
+            .then(InvertFold {})
+            .then(ConsecutiveBuyRemover {})
+            .then(InvertFold {});
+        let folded = pipeline.fold_all(actions.clone(), &trades);
+
+        assert_eq!(folded, manual);
+        // the sell at 11 is kept above the deduplicated buy at 10, at the average of both
+        // buys the loss filter would drop it
+        assert_eq!(folded.len(), 4);
+        assert_ne!(
+            folded,
+            ConsecutiveBuyRemover {}
+                .fold(LossSellRemover {}.fold(actions.clone(), &trades), &trades)
+        );
+
+        let stages = pipeline
+            .build()
+            .iter()
+            .map(|fold| fold.stage())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            stages,
+            vec![
+                FoldStage::Transform,
+                FoldStage::Transform,
+                FoldStage::Dedup,
+                FoldStage::Risk
+            ]
+        );
+    }
+
+    #[test]
+    fn unittest_composite_strategy() {
+        let trades = trades_from_closes(&[100.0; 8]);
+        let fixed = |buys: &[usize], sells: &[usize], price: f64| -> Box<dyn BuySellStrategy> {
+            let buys = buys.iter().map(|ix| (date(*ix), Action::Buy(price)));
+            let sells = sells.iter().map(|ix| (date(*ix), Action::Sell(price)));
+            Box::new(FixedStrategy(buys.chain(sells).collect()))
+        };
+        let composite = |mode| CompositeStrategy {
+            children: vec![
+                fixed(&[1, 2, 3], &[5], 10.0),
+                fixed(&[2, 3, 4], &[5, 6], 20.0),
+            ],
+            mode,
+        };
+
+        assert_eq!(
+            composite(CombineMode::All).buy_sell(&trades),
+            vec![
+                (date(2), Action::Buy(10.0)),
+                (date(3), Action::Buy(10.0)),
+                (date(5), Action::Sell(10.0)),
+            ]
+        );
+        assert_eq!(
+            composite(CombineMode::Any).buy_sell(&trades),
+            vec![
+                (date(1), Action::Buy(10.0)),
+                (date(2), Action::Buy(10.0)),
+                (date(3), Action::Buy(10.0)),
+                (date(4), Action::Buy(20.0)),
+                (date(5), Action::Sell(10.0)),
+                (date(6), Action::Sell(20.0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn unittest_z_score_strategy() {
+        let strategy = ZScoreStrategy {
+            window: 20,
+            entry: 2.0,
+            exit: 2.0,
+        };
+        // 99 and 101 alternating have a mean of 100 and a sample deviation of sqrt(20 / 19)
+        let alternating =
+            |count: usize| (0..count).map(|ix| if ix % 2 == 0 { 99.0 } else { 101.0 });
+        let sigma = (20.0f64 / 19.0).sqrt();
+
+        for (depth, buys) in [(2.5, true), (1.5, false)] {
+            let dip = 100.0 - depth * sigma;
+            let closes = alternating(20)
+                .chain([dip])
+                .chain(alternating(5))
+                .chain([104.0])
+                .collect::<Vec<_>>();
+            let actions = strategy.buy_sell(&trades_from_closes(&closes));
+
+            let mut expected = vec![(date(26), Action::Sell(104.0))];
+            if buys {
+                expected.insert(0, (date(20), Action::Buy(dip)));
+            }
+            assert_eq!(actions, expected, "depth: {depth}");
+        }
+    }
+
+    #[test]
+    fn unittest_volume_spike_strategy() {
+        let strategy = VolumeSpikeStrategy {
+            lookback: 5,
+            multiple: 3.0,
+        };
+
+        let closes = (0..30).map(|ix| 100.0 + ix as f64).collect::<Vec<_>>();
+        let mut trades = trades_from_closes(&closes);
+        // an up close on a surge, a down close on a surge and a surge without a full window
+        for (ix, volume, close) in [(2, 5000, 103.0), (10, 5000, 111.0), (20, 4000, 110.0)] {
+            let bar = trades.get_mut(&date(ix)).unwrap();
+            bar.volume = volume;
+            bar.close = close;
+        }
+        // volume that rises gradually is not a spike
+        for ix in 25..30 {
+            trades.get_mut(&date(ix)).unwrap().volume = 1000 * (ix - 23);
+        }
+
+        assert_eq!(
+            strategy.buy_sell(&trades),
+            vec![
+                (date(10), Action::Buy(111.0)),
+                (date(20), Action::Sell(110.0))
+            ]
+        );
+    }
+
+    #[test]
+    fn unittest_moving_average_cross_strategy() {
+        // down to a trough on day 20, up to a peak on day 50, down again
+        let closes = (0..80)
+            .map(|ix| match ix {
+                0..=20 => 100.0 - ix as f64,
+                21..=50 => 80.0 + (ix - 20) as f64,
+                _ => 110.0 - (ix - 50) as f64,
+            })
+            .collect::<Vec<_>>();
+        let trades = trades_from_closes(&closes);
+
+        for use_ema in [false, true] {
+            let actions = MovingAverageCrossStrategy {
+                short: 3,
+                long: 10,
+                use_ema,
+            }
+            .buy_sell(&trades);
+
+            assert_eq!(actions.len(), 2, "use_ema: {use_ema}");
+            assert!(
+                matches!(actions[0], (day, Action::Buy(_)) if day > date(20) && day <= date(27))
+            );
+            assert!(
+                matches!(actions[1], (day, Action::Sell(_)) if day > date(50) && day <= date(57))
+            );
+        }
+    }
+
+    #[test]
+    fn unittest_beginning_surprise_strategy() {
+        let trades = trades_from_closes(&[100.0, 101.0, 113.12, 114.0, 110.0, 111.0]);
+
+        let actions = BeginningSurpriseStrategy { rise: 0.1 }.buy_sell(&trades);
+
+        assert_eq!(actions, vec![(date(3), Action::Buy(114.0))]);
+    }
+
+    #[test]
+    fn unittest_kaufman_ama_strategy() {
+        let strategy = KaufmanAmaStrategy {
+            period: 5,
+            fast: 2,
+            slow: 30,
+        };
+        let actions = |turn: f64| {
+            let closes = (0..60)
+                .map(|ix| 100.0 + turn * (ix as f64 - 30.0).abs())
+                .collect::<Vec<_>>();
+            strategy.buy_sell(&trades_from_closes(&closes))
+        };
+
+        let trough = actions(1.0);
+        assert_eq!(trough.len(), 1);
+        assert!(matches!(trough[0], (day, Action::Buy(_)) if day > date(30) && day <= date(33)));
+
+        let peak = actions(-1.0);
+        assert_eq!(peak.len(), 1);
+        assert!(matches!(peak[0], (day, Action::Sell(_)) if day > date(30) && day <= date(33)));
     }
 }
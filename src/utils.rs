@@ -1,23 +1,673 @@
-use crate::model::Price;
+use std::collections::VecDeque;
 
-#[derive(Default)]
+use crate::model::{DaySeriesData, Price};
+
+/// mean of the last `window` fed values, run length encoded so feeding a value many times
+/// stays cheap
+#[derive(Debug, Clone)]
 pub struct MovingAverage {
-    value: Price,
+    window: usize,
+    // (value, times) oldest first
+    runs: VecDeque<(Price, usize)>,
+    sum: Price,
     length: usize,
 }
 
 impl MovingAverage {
+    pub fn new(window: usize) -> Self {
+        Self {
+            window,
+            runs: VecDeque::new(),
+            sum: 0.0,
+            length: 0,
+        }
+    }
+
+    /// feeds `value` as `times` samples
     pub fn feed(&mut self, value: Price, times: usize) {
-        self.value += value * times as Price;
+        let times = times.min(self.window);
+        if times == 0 {
+            return;
+        }
+
+        self.runs.push_back((value, times));
+        self.sum += value * times as Price;
         self.length += times;
+
+        while self.length > self.window {
+            let overflow = self.length - self.window;
+            let (oldest, count) = self.runs.front_mut().unwrap();
+            let dropped = overflow.min(*count);
+
+            self.sum -= *oldest * dropped as Price;
+            self.length -= dropped;
+            *count -= dropped;
+            if *count == 0 {
+                self.runs.pop_front();
+            }
+        }
     }
 
     pub fn clear(&mut self) {
-        self.value = Price::default();
+        self.runs.clear();
+        self.sum = Price::default();
         self.length = 0;
     }
 
+    /// samples currently in the window
+    pub fn len(&self) -> usize {
+        self.length
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
     pub fn avg(&self) -> f64 {
-        self.value / self.length as Price
+        self.sum / self.length as Price
+    }
+}
+
+/// population and sample standard deviation of the last `window` fed values, recomputed from
+/// the window on every call
+#[derive(Debug, Clone)]
+pub struct MovingStdDev {
+    window: usize,
+    values: VecDeque<Price>,
+}
+
+impl MovingStdDev {
+    pub fn new(window: usize) -> Self {
+        Self {
+            window,
+            values: VecDeque::with_capacity(window),
+        }
+    }
+
+    pub fn feed(&mut self, value: Price) {
+        if self.window == 0 {
+            return;
+        }
+        if self.values.len() == self.window {
+            self.values.pop_front();
+        }
+        self.values.push_back(value);
+    }
+
+    /// samples currently in the window
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// whether `window` values are in
+    pub fn is_full(&self) -> bool {
+        self.window != 0 && self.values.len() == self.window
+    }
+
+    pub fn mean(&self) -> f64 {
+        self.values.iter().sum::<Price>() / self.values.len() as Price
+    }
+
+    pub fn std_dev(&self) -> f64 {
+        let mean = self.mean();
+        let variance = self
+            .values
+            .iter()
+            .map(|value| (value - mean).powi(2))
+            .sum::<f64>()
+            / self.values.len() as f64;
+        variance.sqrt()
+    }
+
+    /// sample standard deviation, 0.0 with fewer than two values in
+    pub fn std(&self) -> f64 {
+        if self.values.len() < 2 {
+            return 0.0;
+        }
+
+        let mean = self.mean();
+        let variance = self
+            .values
+            .iter()
+            .map(|value| (value - mean).powi(2))
+            .sum::<f64>()
+            / (self.values.len() - 1) as f64;
+        variance.sqrt()
+    }
+
+    /// distance between bollinger bands `num_std` population deviations around the mean, as a
+    /// fraction of the mean. 0.0 with fewer than two values in
+    pub fn bollinger_width(&self, num_std: f64) -> f64 {
+        if self.values.len() < 2 {
+            return 0.0;
+        }
+
+        2.0 * num_std * self.std_dev() / self.mean()
+    }
+}
+
+/// simple moving average aligned with `values`, `None` until `period` values are in
+pub fn sma(values: &[Price], period: usize) -> Vec<Option<Price>> {
+    let mut sum = 0f64;
+
+    values
+        .iter()
+        .enumerate()
+        .map(|(ix, value)| {
+            sum += value;
+            if ix >= period {
+                sum -= values[ix - period];
+            }
+            (period != 0 && ix + 1 >= period).then(|| sum / period as Price)
+        })
+        .collect()
+}
+
+/// exponential moving average with smoothing `2 / (period + 1)`, seeded with the simple average
+/// of the first `period` values, `None` before it
+pub fn ema(values: &[Price], period: usize) -> Vec<Option<Price>> {
+    let alpha = 2.0 / (period as f64 + 1.0);
+    let mut ema: Option<Price> = None;
+
+    sma(values, period)
+        .into_iter()
+        .zip(values)
+        .map(|(sma, value)| {
+            ema = match ema {
+                Some(prev) => Some(prev + alpha * (value - prev)),
+                None => sma,
+            };
+            ema
+        })
+        .collect()
+}
+
+/// |net change| / sum of |daily changes| over the last `period` changes aligned with `values`,
+/// 1.0 in a straight trend and near 0.0 in noise, `None` until `period` changes are in
+pub fn efficiency_ratio(values: &[Price], period: usize) -> Vec<Option<f64>> {
+    (0..values.len())
+        .map(|ix| {
+            if period == 0 || ix < period {
+                return None;
+            }
+
+            let window = &values[ix - period..=ix];
+            let path = window.windows(2).map(|w| (w[1] - w[0]).abs()).sum::<f64>();
+            Some(if path == 0.0 {
+                0.0
+            } else {
+                (window[period] - window[0]).abs() / path
+            })
+        })
+        .collect()
+}
+
+/// kaufman adaptive moving average, an ema whose smoothing moves between the `fast` and `slow`
+/// period ones with the efficiency ratio, seeded with the value once the ratio is available
+pub fn kama(values: &[Price], period: usize, fast: usize, slow: usize) -> Vec<Option<Price>> {
+    let fast_sc = 2.0 / (fast as f64 + 1.0);
+    let slow_sc = 2.0 / (slow as f64 + 1.0);
+    let mut kama: Option<Price> = None;
+
+    values
+        .iter()
+        .zip(efficiency_ratio(values, period))
+        .map(|(value, er)| {
+            let er = er?;
+            let sc = (er * (fast_sc - slow_sc) + slow_sc).powi(2);
+            let next = kama.map_or(*value, |prev| prev + sc * (value - prev));
+            kama = Some(next);
+            kama
+        })
+        .collect()
+}
+
+/// relative strength index with wilder's smoothing of the average gain and loss, `None` until
+/// `period` changes are in. 100.0 without any loss and 50.0 without any change
+pub fn rsi(values: &[Price], period: usize) -> Vec<Option<f64>> {
+    let mut result = vec![None; values.len()];
+    if period == 0 || values.len() <= period {
+        return result;
+    }
+
+    let changes = values.windows(2).map(|w| w[1] - w[0]).collect::<Vec<_>>();
+    let period_f = period as f64;
+    let mut gain = changes[..period].iter().map(|c| c.max(0.0)).sum::<f64>() / period_f;
+    let mut loss = changes[..period].iter().map(|c| (-c).max(0.0)).sum::<f64>() / period_f;
+
+    for ix in period..values.len() {
+        if ix > period {
+            let change = changes[ix - 1];
+            gain = (gain * (period_f - 1.0) + change.max(0.0)) / period_f;
+            loss = (loss * (period_f - 1.0) + (-change).max(0.0)) / period_f;
+        }
+
+        result[ix] = Some(if loss == 0.0 {
+            if gain == 0.0 {
+                50.0
+            } else {
+                100.0
+            }
+        } else {
+            100.0 - 100.0 / (1.0 + gain / loss)
+        });
+    }
+
+    result
+}
+
+/// `high - low` of every bar widened to the close before it, the first bar has no previous close
+pub fn true_range(bars: &[DaySeriesData]) -> Vec<Price> {
+    bars.iter()
+        .enumerate()
+        .map(
+            |(ix, bar)| match ix.checked_sub(1).map(|prev| bars[prev].close) {
+                Some(prev_close) => bar.high.max(prev_close) - bar.low.min(prev_close),
+                None => bar.high - bar.low,
+            },
+        )
+        .collect()
+}
+
+/// average true range with wilder's smoothing, seeded with the mean of the first `period` true
+/// ranges. `None` before the `period`th bar
+pub fn atr(bars: &[DaySeriesData], period: usize) -> Vec<Option<Price>> {
+    let mut result = vec![None; bars.len()];
+    if period == 0 || bars.len() < period {
+        return result;
+    }
+
+    let ranges = true_range(bars);
+    let period_f = period as f64;
+    let mut atr = ranges[..period].iter().sum::<Price>() / period_f;
+    result[period - 1] = Some(atr);
+
+    for ix in period..bars.len() {
+        atr = (atr * (period_f - 1.0) + ranges[ix]) / period_f;
+        result[ix] = Some(atr);
+    }
+
+    result
+}
+
+/// every feed scales the weight of everything fed before it by `decay`,
+/// a decay of 1.0 behaves like an unbounded `MovingAverage`
+pub struct WeightedMovingAverage {
+    decay: f64,
+    value: Price,
+    weight: f64,
+}
+
+impl Default for WeightedMovingAverage {
+    fn default() -> Self {
+        Self::new(1.0)
+    }
+}
+
+impl WeightedMovingAverage {
+    pub fn new(decay: f64) -> Self {
+        Self {
+            decay,
+            value: Price::default(),
+            weight: 0.0,
+        }
+    }
+
+    pub fn feed(&mut self, value: Price, times: usize) {
+        self.value = self.value * self.decay + value * times as Price;
+        self.weight = self.weight * self.decay + times as f64;
+    }
+
+    pub fn clear(&mut self) {
+        self.value = Price::default();
+        self.weight = 0.0;
+    }
+
+    pub fn avg(&self) -> f64 {
+        self.value / self.weight
+    }
+}
+
+/// streaming exponential moving average with `alpha = 2 / (period + 1)`, seeded with the first
+/// sample unlike `ema` which is seeded with the sma of the first `period` values
+#[derive(Debug, Copy, Clone)]
+pub struct Ema {
+    alpha: f64,
+    value: Option<Price>,
+}
+
+impl Ema {
+    pub fn new(period: usize) -> Self {
+        Self {
+            alpha: 2.0 / (period as f64 + 1.0),
+            value: None,
+        }
+    }
+
+    pub fn feed(&mut self, price: Price) {
+        self.value = Some(match self.value {
+            Some(value) => value + self.alpha * (price - value),
+            None => price,
+        });
+    }
+
+    /// `None` until the first sample
+    pub fn value(&self) -> Option<Price> {
+        self.value
+    }
+}
+
+/// small seeded generator (splitmix64) for reproducible noise
+#[derive(Debug, Clone)]
+pub struct SplitMix64(u64);
+
+impl SplitMix64 {
+    pub fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    /// uniform in `[0, 1)`
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// least squares line over the last `window` values
+pub struct MovingRegression {
+    window: usize,
+    values: VecDeque<Price>,
+}
+
+impl MovingRegression {
+    pub fn new(window: usize) -> Self {
+        Self {
+            window,
+            values: VecDeque::with_capacity(window + 1),
+        }
+    }
+
+    pub fn push(&mut self, value: Price) {
+        self.values.push_back(value);
+        if self.values.len() > self.window {
+            self.values.pop_front();
+        }
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.values.len() == self.window
+    }
+
+    /// (slope, intercept) where x is 0 for the oldest value in the window
+    pub fn fit(&self) -> Option<(f64, f64)> {
+        let n = self.values.len() as f64;
+        if self.values.len() < 2 {
+            return None;
+        }
+
+        let x_mean = (n - 1.0) / 2.0;
+        let y_mean = self.values.iter().sum::<f64>() / n;
+
+        let (sxy, sxx) = self
+            .values
+            .iter()
+            .enumerate()
+            .fold((0f64, 0f64), |(sxy, sxx), (x, y)| {
+                let dx = x as f64 - x_mean;
+                (sxy + dx * (y - y_mean), sxx + dx * dx)
+            });
+
+        let slope = sxy / sxx;
+        Some((slope, y_mean - slope * x_mean))
+    }
+
+    /// value of the fitted line `ahead` steps after the newest value
+    pub fn project(&self, ahead: usize) -> Option<f64> {
+        let (slope, intercept) = self.fit()?;
+        Some(intercept + slope * (self.values.len() - 1 + ahead) as f64)
+    }
+
+    /// standard deviation of the residuals around the fitted line
+    pub fn residual_std(&self) -> Option<f64> {
+        let (slope, intercept) = self.fit()?;
+        let sse = self
+            .values
+            .iter()
+            .enumerate()
+            .map(|(x, y)| (y - (intercept + slope * x as f64)).powi(2))
+            .sum::<f64>();
+
+        Some((sse / self.values.len() as f64).sqrt())
+    }
+}
+
+/// solves `matrix * x = rhs` by gaussian elimination with partial pivoting, `None` when the
+/// matrix is singular
+pub fn solve_linear(matrix: &[Vec<f64>], rhs: &[f64]) -> Option<Vec<f64>> {
+    let n = rhs.len();
+    let mut rows = matrix
+        .iter()
+        .zip(rhs)
+        .map(|(row, value)| {
+            let mut row = row.clone();
+            row.push(*value);
+            row
+        })
+        .collect::<Vec<_>>();
+
+    for col in 0..n {
+        let pivot = (col..n).max_by(|l, r| rows[*l][col].abs().total_cmp(&rows[*r][col].abs()))?;
+        if rows[pivot][col].abs() < 1e-15 {
+            return None;
+        }
+        rows.swap(col, pivot);
+
+        let (upper, lower) = rows.split_at_mut(col + 1);
+        let pivot_row = &upper[col];
+        for row in lower {
+            let factor = row[col] / pivot_row[col];
+            for (value, pivot) in row[col..].iter_mut().zip(&pivot_row[col..]) {
+                *value -= factor * pivot;
+            }
+        }
+    }
+
+    let mut x = vec![0f64; n];
+    for row in (0..n).rev() {
+        let known = (row + 1..n).map(|k| rows[row][k] * x[k]).sum::<f64>();
+        x[row] = (rows[row][n] - known) / rows[row][row];
+    }
+
+    Some(x)
+}
+
+/// serde adapter keeping non-finite floats, which json has no number for, as
+/// `"inf"`, `"-inf"` and `"NaN"` strings
+pub mod lossless_float {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &f64, serializer: S) -> Result<S::Ok, S::Error> {
+        if value.is_finite() {
+            serializer.serialize_f64(*value)
+        } else {
+            serializer.serialize_str(&value.to_string())
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Float {
+            Number(f64),
+            Text(String),
+        }
+
+        match Float::deserialize(deserializer)? {
+            Float::Number(value) => Ok(value),
+            Float::Text(text) => text.parse().map_err(D::Error::custom),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        atr, ema, rsi, true_range, Ema, MovingAverage, MovingStdDev, WeightedMovingAverage,
+    };
+    use crate::model::DaySeriesData;
+
+    #[test]
+    fn unittest_weighted_moving_average() {
+        let mut equal = MovingAverage::new(usize::MAX);
+        let mut default = WeightedMovingAverage::default();
+        let mut weighted = WeightedMovingAverage::new(0.5);
+
+        for (value, times) in [(10.0, 1), (20.0, 1)] {
+            equal.feed(value, times);
+            default.feed(value, times);
+            weighted.feed(value, times);
+        }
+
+        assert_eq!(equal.avg(), 15.0);
+        assert_eq!(default.avg(), equal.avg());
+        assert!((weighted.avg() - 25.0 / 1.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn unittest_ema_struct() {
+        let mut ema = Ema::new(3);
+        assert_eq!(ema.value(), None);
+
+        // alpha 0.5, every value is halfway from the previous one to the sample
+        let expected = [
+            2.0, 3.0, 4.5, 6.25, 8.125, 8.0625, 7.03125, 5.515625, 3.7578125, 1.87890625,
+        ];
+        for (price, expected) in [2.0, 4.0, 6.0, 8.0, 10.0, 8.0, 6.0, 4.0, 2.0, 0.0]
+            .into_iter()
+            .zip(expected)
+        {
+            ema.feed(price);
+            assert_eq!(ema.value(), Some(expected));
+        }
+    }
+
+    #[test]
+    fn unittest_moving_average_window() {
+        let mut avg = MovingAverage::new(3);
+        for value in [1.0, 2.0, 3.0, 4.0] {
+            avg.feed(value, 1);
+        }
+        assert_eq!(avg.len(), 3);
+        assert_eq!(avg.avg(), 3.0);
+
+        avg.feed(10.0, 2);
+        assert_eq!(avg.avg(), 8.0);
+
+        avg.feed(5.0, 100);
+        assert_eq!(avg.len(), 3);
+        assert_eq!(avg.avg(), 5.0);
+
+        avg.clear();
+        assert!(avg.is_empty());
+        avg.feed(7.0, 1);
+        assert_eq!(avg.avg(), 7.0);
+    }
+
+    #[test]
+    fn unittest_rsi() {
+        // changes +1 -1 +2 -1 +2, averages (1, 1/3), (2/3, 5/9), (10/9, 10/27)
+        let rsi = rsi(&[10.0, 11.0, 10.0, 12.0, 11.0, 13.0], 3);
+        let expected = [75.0, 100.0 - 100.0 / 2.2, 75.0];
+
+        assert_eq!(rsi[..3], [None, None, None]);
+        for (rsi, expected) in rsi[3..].iter().zip(expected) {
+            assert!((rsi.unwrap() - expected).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn unittest_atr() {
+        let bar = |high: f64, low: f64, close: f64| DaySeriesData {
+            open: close,
+            high,
+            low,
+            close,
+            volume: 0,
+        };
+        let bars = [
+            bar(12.0, 10.0, 11.0),
+            bar(13.0, 11.0, 12.0),
+            // gap up, the range reaches down to the previous close
+            bar(16.0, 14.0, 15.0),
+            // gap down, the range reaches up to the previous close
+            bar(11.0, 9.0, 10.0),
+            bar(11.0, 10.0, 10.5),
+        ];
+
+        assert_eq!(true_range(&bars), vec![2.0, 2.0, 4.0, 6.0, 1.0]);
+        // seeded with (2 + 2 + 4) / 3, then (8/3 * 2 + 6) / 3 and (34/9 * 2 + 1) / 3
+        let atr = atr(&bars, 3);
+        assert_eq!(atr[..2], [None, None]);
+        for (atr, expected) in atr[2..].iter().zip([8.0 / 3.0, 34.0 / 9.0, 77.0 / 27.0]) {
+            assert!((atr.unwrap() - expected).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn unittest_ema() {
+        // seeded with (1 + 2 + 3) / 3, then halfway to every value
+        let ema = ema(&[1.0, 2.0, 3.0, 6.0, 2.0], 3);
+        assert_eq!(ema, vec![None, None, Some(2.0), Some(4.0), Some(3.0)]);
+    }
+
+    #[test]
+    fn unittest_moving_std_dev() {
+        let mut std = MovingStdDev::new(4);
+        for value in [100.0, 2.0, 4.0, 4.0, 4.0] {
+            std.feed(value);
+        }
+        assert!(std.is_full());
+        assert_eq!(std.mean(), 3.5);
+        assert_eq!(std.std_dev(), 0.75f64.sqrt());
+
+        for value in [5.0, 5.0, 7.0, 9.0] {
+            std.feed(value);
+        }
+        assert_eq!(std.len(), 4);
+        assert_eq!(std.std_dev(), 2.75f64.sqrt());
+    }
+
+    #[test]
+    fn unittest_moving_std_dev_sample() {
+        let mut std = MovingStdDev::new(8);
+        std.feed(5.0);
+        assert_eq!(std.std(), 0.0);
+        assert_eq!(std.bollinger_width(2.0), 0.0);
+
+        for _ in 0..10 {
+            std.feed(5.0);
+        }
+        assert_eq!(std.std(), 0.0);
+        assert_eq!(std.bollinger_width(2.0), 0.0);
+
+        // squared deviations from the mean of 5 sum to 32
+        for value in [2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0] {
+            std.feed(value);
+        }
+        assert_eq!(std.mean(), 5.0);
+        assert!((std.std() - (32.0f64 / 7.0).sqrt()).abs() < 1e-12);
+        assert_eq!(std.std_dev(), 2.0);
+        assert_eq!(std.bollinger_width(2.0), 1.6);
     }
 }